@@ -0,0 +1,200 @@
+//! `easl fmt`, which reprints a file from its concrete syntax tree.
+//!
+//! Statements are reprinted token by token with normalized spacing, type ascriptions are
+//! moved directly above the definition they belong to and runs of blank lines are collapsed.
+//! Comments, line breaks and the indentation of the lines continuing a statement are kept.
+//! Lines that don't parse are left untouched.
+
+use crate::parser::{
+    cst::{self, SyntaxElement, SyntaxNode, SyntaxToken, TokenKind},
+    layout::Layout,
+    ParserErrors, Rule,
+};
+use crate::source::Source;
+
+/// A line of the formatted file.
+enum Line {
+    Blank,
+    /// A comment on its own line, or a line that couldn't be parsed.
+    Verbatim(String),
+    Statement {
+        text: String,
+        /// The name a type ascription or definition is for.
+        name: Option<String>,
+        is_ascription: bool,
+    },
+}
+
+pub fn format(source: Source<'_>) -> Result<String, ParserErrors> {
+    let file = cst::parse(source)?;
+
+    let mut lines = Vec::new();
+    // Whether anything has been written on the current line yet.
+    let mut line_started = false;
+    for child in &file.children {
+        match child {
+            SyntaxElement::Node(node) => match node.rule {
+                Rule::statement => {
+                    lines.push(format_statement(node));
+                    // Statements end with their newline.
+                    line_started = false;
+                }
+                Rule::invalid_statement => {
+                    lines.push(Line::Verbatim(node.text().trim_end().to_string()));
+                    line_started = true;
+                }
+                _ => {}
+            },
+            SyntaxElement::Token(token) => match token.kind {
+                // Invalid lines without anything the grammar recognizes in them.
+                TokenKind::Leaf(Rule::invalid_statement) => {
+                    lines.push(Line::Verbatim(token.text.trim_end().to_string()));
+                    line_started = true;
+                }
+                TokenKind::Comment => {
+                    lines.push(Line::Verbatim(token.text.trim_end().to_string()));
+                    line_started = true;
+                }
+                TokenKind::Newline if !line_started => lines.push(Line::Blank),
+                TokenKind::Newline => line_started = false,
+                _ => {}
+            },
+        }
+    }
+
+    move_ascriptions(&mut lines);
+
+    let mut formatted = String::new();
+    let mut previous_blank = true;
+    for line in &lines {
+        let text = match line {
+            Line::Blank if previous_blank => continue,
+            Line::Blank => "",
+            Line::Verbatim(text) | Line::Statement { text, .. } => text,
+        };
+        previous_blank = matches!(line, Line::Blank);
+        formatted.push_str(text);
+        formatted.push('\n');
+    }
+    while formatted.ends_with("\n\n") {
+        formatted.pop();
+    }
+    Ok(formatted)
+}
+
+fn format_statement(statement: &SyntaxNode) -> Line {
+    let tokens = statement.tokens();
+    // Without the newline ending the statement.
+    let end = tokens
+        .iter()
+        .rposition(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Newline))
+        .map_or(0, |last| last + 1);
+    let mut text = String::new();
+    let mut previous: Option<&SyntaxToken> = None;
+    for (index, token) in tokens[..end].iter().enumerate() {
+        match token.kind {
+            TokenKind::Whitespace => continue,
+            TokenKind::Newline => {
+                // The indentation decides what a line belongs to, so it's kept as it is.
+                text.truncate(text.trim_end_matches([' ', '\t']).len());
+                text.push('\n');
+                let indentation = tokens[index + 1..]
+                    .iter()
+                    .take_while(|next| next.kind == TokenKind::Whitespace);
+                text.extend(indentation.map(|token| token.text.as_str()));
+                previous = None;
+                continue;
+            }
+            TokenKind::Comment => {
+                if previous.is_some() {
+                    text.push(' ');
+                }
+                text.push_str(token.text.trim_end());
+                continue;
+            }
+            _ => {}
+        }
+        if previous.is_some_and(|previous| needs_space(previous, token)) {
+            text.push(' ');
+        }
+        text.push_str(&token.text);
+        previous = Some(token);
+    }
+    // Respacing a line can move the start of a block that later lines are aligned with.
+    if text.contains('\n') && separator_lines(&text) != separator_lines(&statement.text()) {
+        text = statement.text().lines().map(str::trim_end).collect::<Vec<_>>().join("\n");
+    }
+
+    let inner = statement.children.iter().find_map(|child| match child {
+        SyntaxElement::Node(node) => Some(node),
+        _ => None,
+    });
+    let is_named = |inner: &&SyntaxNode| {
+        matches!(inner.rule, Rule::assignment | Rule::type_ascription)
+    };
+    let name = inner.filter(is_named).and_then(|inner| {
+        let name = inner.child(Rule::name).map(|name| name.text()).or_else(|| {
+            // `a <+> b = ...`
+            inner.children.iter().find_map(|child| match child {
+                SyntaxElement::Token(token) if token.kind == TokenKind::Leaf(Rule::operator) => {
+                    Some(format!("({})", token.text))
+                }
+                _ => None,
+            })
+        })?;
+        Some(name.split_whitespace().collect())
+    });
+    let is_ascription = inner.is_some_and(|inner| inner.rule == Rule::type_ascription);
+    Line::Statement { text, name, is_ascription }
+}
+
+/// The lines that the layout starts a new item of a block on.
+fn separator_lines(text: &str) -> Vec<usize> {
+    Layout::new(text)
+        .separators
+        .into_iter()
+        .map(|separator| text[..separator].matches('\n').count())
+        .collect()
+}
+
+/// Whether two tokens next to each other in a statement are separated by a space.
+fn needs_space(previous: &SyntaxToken, next: &SyntaxToken) -> bool {
+    let tight_after = ["(", "[", "\\", ".."];
+    let tight_before = [")", "]", ",", ";", ".."];
+    let is_unary = matches!(previous.kind, TokenKind::Leaf(Rule::not | Rule::negative));
+    let is_field = next.kind == TokenKind::Leaf(Rule::field);
+    let is_empty_record = previous.text == "{" && next.text == "}";
+    !(tight_after.contains(&previous.text.as_str())
+        || tight_before.contains(&next.text.as_str())
+        || is_unary
+        || is_field
+        || is_empty_record)
+}
+
+/// Moves every type ascription directly above the definition of the same name.
+fn move_ascriptions(lines: &mut Vec<Line>) {
+    let mut index = 0;
+    while index < lines.len() {
+        let Line::Statement { name: Some(name), is_ascription: true, .. } = &lines[index] else {
+            index += 1;
+            continue;
+        };
+        let definition = lines.iter().position(|line| {
+            matches!(line, Line::Statement { name: Some(other), is_ascription: false, .. }
+                if other == name)
+        });
+        match definition {
+            Some(definition) if definition != index + 1 => {
+                let ascription = lines.remove(index);
+                let definition = if definition > index { definition - 1 } else { definition };
+                lines.insert(definition, ascription);
+                // The line that moved into `index` hasn't been looked at yet, unless the
+                // ascription moved up past it.
+                if definition < index {
+                    index += 1;
+                }
+            }
+            _ => index += 1,
+        }
+    }
+}
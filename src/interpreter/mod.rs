@@ -1,51 +1,216 @@
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
-use miette::{Diagnostic, SourceSpan};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use palette::{encoding::Srgb, white_point::D65, FromColor, Hsva, Srgba, Xyza};
 use thiserror::Error;
 
+use crate::source::{FileId, SourceDb};
 use crate::parser::ast::{
-    BinaryOperator, Expression, IdentifierMap, Primary, Statement,
-    UnaryOperator, Identifier, Type, Spanned,
+    dependency_order, Alternative, BinaryOperator, Builtin, Expression, IdentifierMap, Pattern,
+    NodeId, Primary, Statement, UnaryOperator, Identifier, Type, Spanned,
 };
 
+/// Values bound by lambdas and `let`s, shadowing the top level definitions.
+pub type Scope = HashMap<Identifier, Primary>;
+
 pub struct InterpreterState {
     pub ident_map: IdentifierMap,
 
     pub value_map: HashMap<Identifier, Primary>,
     pub type_map: HashMap<Identifier, Type>,
+    /// The types inferred for expressions, see `typeck::Typed`.
+    pub types: HashMap<NodeId, Type>,
+    /// The methods of the instances of user declared types, by type and method name.
+    pub instances: HashMap<(Identifier, String), Primary>,
+    pub limits: Limits,
+    /// How many more functions the definition being evaluated can call.
+    fuel: Cell<u64>,
+    /// Where the function calls that are being evaluated were made, the innermost last.
+    calls: RefCell<Vec<(FileId, Range<usize>)>>,
+    /// The top level definitions, a definition without a value is being evaluated.
+    definitions: HashSet<Identifier>,
+    /// The address of the bottom of the stack evaluation started at.
+    stack_base: usize,
+}
+
+/// Bounds on how much work evaluating a definition can take, so that a recursive definition
+/// that never stops is reported instead of running forever or overflowing the stack.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// How many functions evaluating a single top level definition can call in total. Building
+    /// a range takes one for each of its elements.
+    pub fuel: u64,
+    /// How many function calls can be evaluated inside each other.
+    pub depth: usize,
+}
+
+impl Limits {
+    /// The deepest nesting of calls that's reasonable to reserve the stack for, `easl run`
+    /// doesn't accept larger depths.
+    pub const MAX_DEPTH: usize = MAX_STACK / STACK_PER_CALL;
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { fuel: 1_000_000, depth: 1_000 }
+    }
 }
 
+/// How much stack to set aside for each function call allowed by `Limits::depth`. A call
+/// takes a few nested evaluations of expressions, which take around half of this in debug
+/// builds. Calls with deeply nested bodies can take more, so the stack that's actually used is
+/// checked as well.
+const STACK_PER_CALL: usize = 64 * 1024;
+const MAX_STACK: usize = 1024 * 1024 * 1024;
+
 pub fn interpret(
     statements: Vec<Statement>,
-    source: &str,
+    sources: &SourceDb,
     ident_map: IdentifierMap,
+    types: HashMap<NodeId, Type>,
+    limits: Limits,
 ) -> Result<(), InterpreterError> {
-    let mut state = InterpreterState { ident_map, value_map: HashMap::new(), type_map: HashMap::new() };
-    for statement in statements {
-        interpret_statement(statement, source, &mut state)?;
+    // Evaluation recurses for every call, so it gets a stack that can hold as many calls as
+    // the depth limit allows. Only the part that's used takes up memory.
+    let stack_size = limits.depth.saturating_mul(STACK_PER_CALL).saturating_add(1024 * 1024);
+    std::thread::scope(|scope| {
+        let run = || interpret_definitions(statements, sources, ident_map, types, limits);
+        let thread = std::thread::Builder::new().stack_size(stack_size).spawn_scoped(scope, run);
+        let thread =
+            thread.map_err(|error| InterpreterError::NoStack { size: stack_size, error })?;
+        thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// The address of a local variable, how far apart two of these are is how much stack was used
+/// between them.
+#[inline(never)]
+fn stack_address() -> usize {
+    let local = 0u8;
+    std::hint::black_box(&local) as *const u8 as usize
+}
+
+fn interpret_definitions(
+    statements: Vec<Statement>,
+    sources: &SourceDb,
+    ident_map: IdentifierMap,
+    types: HashMap<NodeId, Type>,
+    limits: Limits,
+) -> Result<(), InterpreterError> {
+    let value_map = Builtin::ALL
+        .iter()
+        .filter_map(|builtin| {
+            let ident = ident_map.get_from_name(builtin.name())?;
+            // Builtins without arguments are plain values.
+            let value = match builtin {
+                Builtin::Otherwise => Primary::Bool(true),
+                _ => Primary::Builtin { builtin: *builtin, args: Vec::new() },
+            };
+            Some((ident, value))
+        })
+        .collect();
+    let mut state = InterpreterState {
+        ident_map,
+        value_map,
+        type_map: HashMap::new(),
+        types,
+        instances: HashMap::new(),
+        limits,
+        fuel: Cell::new(limits.fuel),
+        calls: RefCell::new(Vec::new()),
+        definitions: HashSet::new(),
+        stack_base: stack_address(),
+    };
+
+    // Definitions are evaluated after the ones they use. Instances come before the
+    // definitions that they don't use, since those might use their methods. Everything
+    // else only declares things and comes first.
+    let (mut definitions, declarations): (Vec<_>, Vec<_>) =
+        statements.into_iter().partition(|statement| {
+            matches!(statement, Statement::Assignment { .. } | Statement::Instance { .. })
+        });
+    for statement in declarations {
+        interpret_statement(statement, sources, &mut state)?;
+    }
+    definitions.sort_by_key(|statement| !matches!(statement, Statement::Instance { .. }));
+    let dependencies: Vec<_> = definitions
+        .iter()
+        .map(|statement| match statement {
+            Statement::Assignment { ident, expr, .. } => {
+                (Some(*ident), expr.inner.free_variables())
+            }
+            Statement::Instance { methods, .. } => {
+                let uses = methods.iter().flat_map(|(_, expr)| expr.inner.free_variables());
+                (None, uses.collect())
+            }
+            _ => unreachable!("only definitions and instances are left"),
+        })
+        .collect();
+    state.definitions = dependencies.iter().filter_map(|(defines, _)| *defines).collect();
+    let mut definitions: Vec<_> = definitions.into_iter().map(Some).collect();
+    for mut group in dependency_order(&dependencies) {
+        // Lambdas don't use anything until they're called, so definitions that use each
+        // other can be evaluated once the lambdas among them have been.
+        group.sort_by_key(|index| match &definitions[*index] {
+            Some(Statement::Assignment { expr, .. }) => !expr.inner.is_lambda(),
+            _ => true,
+        });
+        for index in group {
+            let statement = definitions[index].take().unwrap();
+            state.fuel.set(state.limits.fuel);
+            interpret_statement(statement, sources, &mut state)?;
+        }
     }
     Ok(())
 }
 
-fn execute(state: InterpreterState, position: i64) -> Result<(), InterpreterError> {
+#[allow(dead_code)]
+fn execute(_state: InterpreterState, _position: i64) -> Result<(), InterpreterError> {
     todo!("write the code that actually sets leds");
-    Ok(())
 }
 
 fn interpret_statement(
     statement: Statement,
-    source: &str,
+    sources: &SourceDb,
     state: &mut InterpreterState,
 ) -> Result<(), InterpreterError> {
     match statement {
-        Statement::Assignment { ident, expr } => {
-            let expr = interpret_expression(expr, source)?;
+        Statement::Assignment { ident, expr, .. } => {
+            let expr = interpret_expression(expr, sources, state, &Scope::new())?;
             state.value_map.insert(ident, expr.inner);
         }
         Statement::TypeAscription { ident, type_ } => {
-            state.type_map.insert(ident, type_);
+            state.type_map.insert(ident, type_.inner);
         }
         Statement::Include { .. } => {},
+        Statement::Import { .. } => {},
+        Statement::Record { .. } => {},
+        Statement::TypeAlias { .. } => {},
+        Statement::Data { ident, constructors } => {
+            for (constructor, types) in constructors {
+                let value = Primary::Constructor {
+                    data: ident,
+                    constructor: constructor.inner,
+                    arity: types.len(),
+                    args: Vec::new(),
+                };
+                state.value_map.insert(constructor.inner, value);
+            }
+        }
+        Statement::Instance { type_, methods, .. } => {
+            for (method, expr) in methods {
+                let value = interpret_expression(expr, sources, state, &Scope::new())?;
+                let name = state.ident_map.get(&method.inner).cloned().unwrap_or_default();
+                state.instances.insert((type_.inner, name), value.inner);
+            }
+        }
+        Statement::Fixity { .. } => {},
+        Statement::Error => unreachable!("programs with parser errors are never interpreted"),
         Statement::EOI => (),
     }
 
@@ -54,160 +219,770 @@ fn interpret_statement(
     Ok(())
 }
 
-fn interpret_expression(expression: Spanned<Expression>, source: &str) -> Result<Spanned<Primary>, InterpreterError> {
+fn interpret_expression(
+    expression: Spanned<Expression>,
+    sources: &SourceDb,
+    state: &InterpreterState,
+    scope: &Scope,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let file = expression.file;
     match expression.inner {
         Expression::If { cond, then, else_ } => {
-            let cond = interpret_expression(*cond, source)?;
-            let then = interpret_expression(*then, source)?;
-            let else_ = interpret_expression(*else_, source)?;
+            let cond = interpret_expression(*cond, sources, state, scope)?;
 
             match cond.inner {
-                Primary::Bool(true) => Ok(then),
-                Primary::Bool(false) => Ok(else_),
+                Primary::Bool(true) => interpret_expression(*then, sources, state, scope),
+                Primary::Bool(false) => interpret_expression(*else_, sources, state, scope),
                 _ => Err(InterpreterError::IfConditionWrongType {
-                    source_code: source.to_string(),
+                    source_code: sources.named(file),
                     this_if: expression.span.into(),
                     this_condition: cond.span.into(),
                 }),
             }
         }
         Expression::Binary { operator, lhs, rhs } => {
-            let lhs = interpret_expression(*lhs, source)?;
-            let rhs = interpret_expression(*rhs, source)?;
-
-            if Primary::is_same_type(&lhs.inner, &rhs.inner) {
-                return Err(InterpreterError::BinaryOperandMismatch {
-                    source_code: source.to_string(),
-                    this_binary: expression.span.into(),
-                    this_lhs: lhs.span.into(),
-                    this_rhs: rhs.span.into(),
-                });
-            }
+            let lhs = interpret_expression(*lhs, sources, state, scope)?;
+            let rhs = interpret_expression(*rhs, sources, state, scope)?;
+            binary(operator, lhs, rhs, expression.span, file, sources, state)
+        }
+        Expression::FunctionApplication { function, argument } => {
+            let function = interpret_expression(*function, sources, state, scope)?;
+            let argument = interpret_expression(*argument, sources, state, scope)?;
+            let result = apply(function, argument, expression.span, file, sources, state)?;
+            number_at_type(result, expression.id, sources, state)
+        }
+        Expression::Unary { operator, rhs } => {
+            let rhs = interpret_expression(*rhs, sources, state, scope)?;
 
-            Ok(match operator {
-                BinaryOperator::Equivalent => Spanned {
-                    inner: Primary::Bool(lhs == rhs),
-                    span: expression.span.into(),
-                },
-                BinaryOperator::NotEquivalent => Spanned {
-                    inner: Primary::Bool(lhs != rhs),
-                    span: expression.span.into(),
-                },
-                BinaryOperator::GreaterThan => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Bool(lhs > rhs),
-                        span: expression.span.into(),
-                    }
-                }
-                BinaryOperator::LessThan => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Bool(lhs < rhs),
-                        span: expression.span.into(),
+            match operator {
+                UnaryOperator::Negative => negate(rhs, "-", expression.span, file, sources, state),
+                UnaryOperator::Not => match rhs.inner {
+                    Primary::Bool(rhs) => {
+                        Ok(Spanned::new(file, expression.span, Primary::Bool(!rhs)))
                     }
+                    _ => Err(InterpreterError::NegatedWrongType {
+                        source_code: sources.named(file),
+                        this_op: expression.span.into(),
+                        this_expr: rhs.span.into(),
+                    }),
+                },
+            }
+        }
+        Expression::Let { bindings, body } => {
+            let mut scope = scope.clone();
+            bind(bindings, sources, state, &mut scope)?;
+            interpret_expression(*body, sources, state, &scope)
+        }
+        Expression::Case { scrutinee, arms } => {
+            let scrutinee = interpret_expression(*scrutinee, sources, state, scope)?;
+            for arm in arms {
+                let mut scope = scope.clone();
+                if !match_pattern(&arm.pattern.inner, &scrutinee.inner, &mut scope) {
+                    continue;
                 }
-                BinaryOperator::GreaterThanOrEqual => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Bool(lhs >= rhs),
-                        span: expression.span.into(),
+                bind(arm.bindings, sources, state, &mut scope)?;
+                for Alternative { guard, body } in arm.alternatives {
+                    if let Some(guard) = guard {
+                        let guard = interpret_expression(guard, sources, state, &scope)?;
+                        match guard.inner {
+                            Primary::Bool(true) => {}
+                            Primary::Bool(false) => continue,
+                            _ => {
+                                return Err(InterpreterError::GuardWrongType {
+                                    source_code: sources.named(file),
+                                    this_guard: guard.span.into(),
+                                })
+                            }
+                        }
                     }
+                    return interpret_expression(body, sources, state, &scope);
                 }
-                BinaryOperator::LessThanOrEqual => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Bool(lhs <= rhs),
-                        span: expression.span.into(),
+            }
+            Err(InterpreterError::NonExhaustiveCase {
+                source_code: sources.named(file),
+                value: scrutinee.inner.display(&state.ident_map).to_string(),
+                this_scrutinee: scrutinee.span.into(),
+            })
+        }
+        Expression::Array(elements) => Ok(Spanned::new(
+            file,
+            expression.span,
+            Primary::Array(
+                elements
+                    .into_iter()
+                    .map(|element| Ok(interpret_expression(element, sources, state, scope)?.inner))
+                    .collect::<Result<_, _>>()?,
+            ),
+        )),
+        Expression::Tuple(elements) => Ok(Spanned::new(
+            file,
+            expression.span,
+            Primary::Tuple(
+                elements
+                    .into_iter()
+                    .map(|element| Ok(interpret_expression(element, sources, state, scope)?.inner))
+                    .collect::<Result<_, _>>()?,
+            ),
+        )),
+        Expression::Record { name, fields } => Ok(Spanned::new(
+            file,
+            expression.span,
+            Primary::Record {
+                name,
+                fields: fields
+                    .into_iter()
+                    .map(|(field, value)| {
+                        Ok((field.inner, interpret_expression(value, sources, state, scope)?.inner))
+                    })
+                    .collect::<Result<_, _>>()?,
+            },
+        )),
+        Expression::FieldAccess { record, field } => {
+            let record = interpret_expression(*record, sources, state, scope)?;
+            let value = match &record.inner {
+                Primary::Record { fields, .. } => fields
+                    .iter()
+                    .find(|(ident, _)| *ident == field.inner)
+                    .map(|(_, value)| value.clone()),
+                _ => None,
+            };
+            value
+                .map(|value| Spanned::new(file, expression.span.clone(), value))
+                .ok_or_else(|| InterpreterError::NoSuchField {
+                    source_code: sources.named(file),
+                    field: state.ident_map.get(&field.inner).cloned().unwrap_or_default(),
+                    value: record.inner.display(&state.ident_map).to_string(),
+                    this_record: record.span.into(),
+                    this_field: field.span.into(),
+                })
+        }
+        Expression::Range { start, end } => {
+            let start = interpret_expression(*start, sources, state, scope)?;
+            let end = interpret_expression(*end, sources, state, scope)?;
+            let (Primary::Int(first), Primary::Int(last)) = (&start.inner, &end.inner) else {
+                let bound = if matches!(start.inner, Primary::Int(_)) { end } else { start };
+                return Err(InterpreterError::RangeBoundWrongType {
+                    source_code: sources.named(file),
+                    this_bound: bound.span.into(),
+                });
+            };
+            // Every element costs as much as a call, so a huge range is reported instead of
+            // exhausting the memory.
+            let length = (i128::from(*last) - i128::from(*first) + 1).max(0);
+            let fuel = state.fuel.get();
+            if length > i128::from(fuel) {
+                return Err(InterpreterError::RangeTooLong {
+                    source_code: sources.named(file),
+                    length: length.to_string(),
+                    fuel,
+                    this_range: expression.span.into(),
+                });
+            }
+            state.fuel.set(fuel - length as u64);
+            let elements = (*first..=*last).map(Primary::Int).collect();
+            Ok(Spanned::new(file, expression.span, Primary::Array(elements)))
+        }
+        Expression::Error => unreachable!("programs with parser errors are never interpreted"),
+        Expression::Variable(identifier) => {
+            let value = scope
+                .get(&identifier)
+                .or_else(|| state.value_map.get(&identifier))
+                .map(|value| Spanned::new(file, expression.span.clone(), value.clone()))
+                .ok_or_else(|| {
+                let source_code = sources.named(file);
+                let ident = state.ident_map.get(&identifier).cloned().unwrap_or_default();
+                let this_variable = expression.span.into();
+                    match state.definitions.contains(&identifier) {
+                        true => InterpreterError::CyclicDefinition {
+                            source_code,
+                            ident,
+                            this_variable,
+                        },
+                        false => InterpreterError::UnboundIdentifier {
+                            source_code,
+                            ident,
+                            this_variable,
+                        },
                     }
-                }
+                })?;
+            number_at_type(value, expression.id, sources, state)
+        }
+        Expression::Primary(Spanned {
+            file,
+            inner: Primary::Lambda { param, body },
+            span,
+            ..
+        }) => Ok(Spanned::new(
+            file,
+            span,
+            Primary::Closure {
+                param,
+                body,
+                captures: scope.clone(),
+            },
+        )),
+        Expression::Annotated { expr, .. } => interpret_expression(*expr, sources, state, scope),
+        Expression::Primary(primary) => {
+            number_at_type(primary, expression.id, sources, state)
+        }
+    }
+}
 
-                BinaryOperator::Add => match (lhs.inner, rhs.inner) {
-                    (Primary::Int(lhs), Primary::Int(rhs)) => Spanned {
-                        inner: Primary::Int(lhs + rhs),
-                        span: expression.span.into(),
-                    },
-                    (Primary::String(lhs), Primary::String(rhs)) => Spanned {
-                        inner: Primary::String(lhs + &rhs),
-                        span: expression.span.into(),
-                    },
-                    _ => todo!(),
-                },
-                BinaryOperator::Sub => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Int(lhs - rhs),
-                        span: expression.span.into(),
-                    }
-                }
+/// Adds the `bindings` of a `let` or of a case arm to `scope`, each one in scope for the ones
+/// after it.
+fn bind(
+    bindings: Vec<(Spanned<Pattern>, Spanned<Expression>)>,
+    sources: &SourceDb,
+    state: &InterpreterState,
+    scope: &mut Scope,
+) -> Result<(), InterpreterError> {
+    for (pattern, expr) in bindings {
+        let value = interpret_expression(expr, sources, state, scope)?;
+        if !match_pattern(&pattern.inner, &value.inner, scope) {
+            return Err(InterpreterError::LetPatternMismatch {
+                source_code: sources.named(pattern.file),
+                value: value.inner.display(&state.ident_map).to_string(),
+                this_pattern: pattern.span.into(),
+                this_value: value.span.into(),
+            });
+        }
+    }
+    Ok(())
+}
 
-                BinaryOperator::Mul => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Int(lhs * rhs),
-                        span: expression.span.into(),
-                    }
-                }
-                BinaryOperator::Div => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Int(lhs / rhs),
-                        span: expression.span.into(),
-                    }
-                }
-                BinaryOperator::Remainder => {
-                    let (Primary::Int(lhs), Primary::Int(rhs)) = (lhs.inner, rhs.inner) else {
-                        todo!()
-                    };
-                    Spanned {
-                        inner: Primary::Int(lhs % rhs),
-                        span: expression.span.into(),
+/// Number literals are `Int`s or `Float`s, and so are the numbers generic functions and
+/// definitions make out of them. Where the expression `id` has another type, the
+/// number becomes a value of that type. Inside generic functions it's converted once it's
+/// combined with a value of the type instead, see `promote` and `binary`.
+fn number_at_type(
+    value: Spanned<Primary>,
+    id: NodeId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let (number, from) = match value.inner {
+        Primary::Int(int) => (int as f64, "fromInt"),
+        Primary::Float(float) => (float, "fromFloat"),
+        _ => return Ok(value),
+    };
+    let inner = match state.types.get(&id).map(Type::expand_aliases) {
+        Some(Type::Float) => Primary::Float(number),
+        Some(Type::Color) => Primary::Color(number_color(number)),
+        Some(Type::Named(type_)) => {
+            let Some(from) = state.instances.get(&(type_, from.to_string())) else {
+                return Ok(value);
+            };
+            let (file, span) = (value.file, value.span.clone());
+            return call(from, vec![value], span, file, sources, state);
+        }
+        _ => return Ok(value),
+    };
+    Ok(Spanned { inner, ..value })
+}
+
+/// Evaluates `lhs operator rhs`. Values of user declared types use the methods of their
+/// instances, `!=`, `>`, `<=` and `>=` being defined by `==` and `<`. Colors are combined
+/// component by component, the alpha being kept between 0 and 1.
+fn binary(
+    operator: BinaryOperator,
+    lhs: Spanned<Primary>,
+    rhs: Spanned<Primary>,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    if let BinaryOperator::Index = operator {
+        return index(lhs, rhs, span, file, sources, state);
+    }
+    let instance_method = |name: &str| {
+        method(state, &lhs.inner, name).or_else(|| method(state, &rhs.inner, name)).cloned()
+    };
+
+    let name = match operator {
+        BinaryOperator::NotEquivalent => "==",
+        BinaryOperator::GreaterThan
+        | BinaryOperator::GreaterThanOrEqual
+        | BinaryOperator::LessThanOrEqual => "<",
+        _ => operator.symbol(),
+    };
+    if let Some(function) = instance_method(name) {
+        // Number literals of the type are still numbers.
+        let (from_int, from_float) = (instance_method("fromInt"), instance_method("fromFloat"));
+        let convert = |value: Spanned<Primary>| {
+            let from = match &value.inner {
+                Primary::Int(_) => &from_int,
+                Primary::Float(_) => &from_float,
+                _ => &None,
+            };
+            match from {
+                Some(from) => call(from, vec![value], span.clone(), file, sources, state),
+                None => Ok(value),
+            }
+        };
+        let (lhs, rhs) = (convert(lhs)?, convert(rhs)?);
+        let args = match operator {
+            BinaryOperator::GreaterThan | BinaryOperator::LessThanOrEqual => vec![rhs, lhs],
+            _ => vec![lhs, rhs],
+        };
+        let result = call(&function, args, span.clone(), file, sources, state)?;
+        let negated = matches!(
+            operator,
+            BinaryOperator::NotEquivalent
+                | BinaryOperator::LessThanOrEqual
+                | BinaryOperator::GreaterThanOrEqual
+        );
+        return Ok(match (negated, result.inner) {
+            (true, Primary::Bool(result)) => Spanned::new(file, span, Primary::Bool(!result)),
+            (_, result) => Spanned::new(file, span, result),
+        });
+    }
+
+    let (mut lhs, mut rhs) = (lhs, rhs);
+    let lhs_value = promote(lhs.inner, &rhs.inner);
+    rhs.inner = promote(rhs.inner, &lhs_value);
+    lhs.inner = lhs_value;
+    if !Primary::is_same_type(&lhs.inner, &rhs.inner) {
+        return Err(InterpreterError::BinaryOperandMismatch {
+            source_code: sources.named(file),
+            this_binary: span.into(),
+            this_lhs: lhs.span.into(),
+            this_rhs: rhs.span.into(),
+        });
+    }
+
+    let no_instance = InterpreterError::NoInstance {
+        source_code: sources.named(file),
+        operator: operator.symbol(),
+        expected: match operator.class() {
+            Some(class) => format!("an instance of {}", class.name()),
+            None => "an Int".to_string(),
+        },
+        this_operation: span.clone().into(),
+        this_operand: lhs.span.clone().into(),
+    };
+    let inner = match operator {
+        BinaryOperator::Equivalent => Primary::Bool(lhs.inner == rhs.inner),
+        BinaryOperator::NotEquivalent => Primary::Bool(lhs.inner != rhs.inner),
+        BinaryOperator::GreaterThan
+        | BinaryOperator::LessThan
+        | BinaryOperator::GreaterThanOrEqual
+        | BinaryOperator::LessThanOrEqual => {
+            let ordering = match (&lhs.inner, &rhs.inner) {
+                (Primary::Int(lhs), Primary::Int(rhs)) => Some(lhs.cmp(rhs)),
+                (Primary::Float(lhs), Primary::Float(rhs)) => lhs.partial_cmp(rhs),
+                (Primary::String(lhs), Primary::String(rhs)) => Some(lhs.cmp(rhs)),
+                _ => return Err(no_instance),
+            };
+            Primary::Bool(match operator {
+                BinaryOperator::GreaterThan => ordering == Some(Ordering::Greater),
+                BinaryOperator::LessThan => ordering == Some(Ordering::Less),
+                BinaryOperator::GreaterThanOrEqual => ordering.is_some_and(Ordering::is_ge),
+                _ => ordering.is_some_and(Ordering::is_le),
+            })
+        }
+        _ => match (lhs.inner, rhs.inner) {
+            (Primary::String(lhs), Primary::String(rhs))
+                if matches!(operator, BinaryOperator::Add) =>
+            {
+                Primary::String(lhs + &rhs)
+            }
+            (Primary::Int(lhs), Primary::Int(divisor)) => {
+                let int = int_arithmetic(&operator, lhs, divisor).ok_or_else(|| {
+                    InterpreterError::DivisionByZero {
+                        source_code: sources.named(file),
+                        this_divisor: rhs.span.clone().into(),
                     }
-                }
+                })?;
+                Primary::Int(int)
+            }
+            (Primary::Float(lhs), Primary::Float(rhs)) if operator.class().is_some() => {
+                Primary::Float(arithmetic(&operator, lhs, rhs))
+            }
+            (Primary::Color(lhs), Primary::Color(rhs)) if operator.class().is_some() => {
+                let component = |lhs, rhs| arithmetic(&operator, lhs, rhs);
+                Primary::Color(Xyza::new(
+                    component(lhs.x, rhs.x),
+                    component(lhs.y, rhs.y),
+                    component(lhs.z, rhs.z),
+                    component(lhs.alpha, rhs.alpha).clamp(0.0, 1.0),
+                ))
+            }
+            _ => return Err(no_instance),
+        },
+    };
+    Ok(Spanned::new(file, span, inner))
+}
+
+/// Evaluates an arithmetic `operator` on two `Float`s or color components.
+fn arithmetic(operator: &BinaryOperator, lhs: f64, rhs: f64) -> f64 {
+    match operator {
+        BinaryOperator::Add => lhs + rhs,
+        BinaryOperator::Sub => lhs - rhs,
+        BinaryOperator::Mul => lhs * rhs,
+        _ => lhs / rhs,
+    }
+}
+
+/// Evaluates an arithmetic or bitwise `operator` on two `Int`s, wrapping around when the
+/// result doesn't fit. `%` is never negative, and shifting by 64 or more bits, or by a
+/// negative number of them, shifts every bit out. `None` when dividing by zero.
+fn int_arithmetic(operator: &BinaryOperator, lhs: i64, rhs: i64) -> Option<i64> {
+    let shift = u32::try_from(rhs).ok();
+    Some(match operator {
+        BinaryOperator::Add => lhs.wrapping_add(rhs),
+        BinaryOperator::Sub => lhs.wrapping_sub(rhs),
+        BinaryOperator::Mul => lhs.wrapping_mul(rhs),
+        BinaryOperator::Div if rhs != 0 => lhs.wrapping_div(rhs),
+        BinaryOperator::Remainder if rhs != 0 => lhs.wrapping_rem_euclid(rhs),
+        BinaryOperator::BitAnd => lhs & rhs,
+        BinaryOperator::BitOr => lhs | rhs,
+        BinaryOperator::BitXor => lhs ^ rhs,
+        BinaryOperator::ShiftLeft => shift.and_then(|shift| lhs.checked_shl(shift)).unwrap_or(0),
+        BinaryOperator::ShiftRight => {
+            shift.and_then(|shift| lhs.checked_shr(shift)).unwrap_or(lhs.signum().min(0))
+        }
+        _ => return None,
+    })
+}
+
+/// Number literals are `Int`s or `Float`s until they're combined with a value of the type
+/// they were inferred to have: a `Float`, or a color, see `number_color`.
+fn promote(value: Primary, other: &Primary) -> Primary {
+    match (value, other) {
+        (Primary::Int(number), Primary::Float(_) | Primary::Color(_)) => {
+            promote(Primary::Float(number as f64), other)
+        }
+        (Primary::Float(number), Primary::Color(_)) => Primary::Color(number_color(number)),
+        (value, _) => value,
+    }
+}
+
+/// The opaque color a number stands for, with every component but alpha that number.
+fn number_color(number: f64) -> Xyza<D65, f64> {
+    Xyza::new(number, number, number, 1.0)
+}
+
+/// Evaluates `operator value`, `-` or `negate`. Colors keep their alpha.
+fn negate(
+    value: Spanned<Primary>,
+    operator: &'static str,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let inner = match &value.inner {
+        Primary::Int(number) => Primary::Int(number.wrapping_neg()),
+        Primary::Float(number) => Primary::Float(-number),
+        Primary::Color(color) => {
+            Primary::Color(Xyza::new(-color.x, -color.y, -color.z, color.alpha))
+        }
+        other => match method(state, other, "negate") {
+            Some(function) => return call(function, vec![value], span, file, sources, state),
+            None => {
+                return Err(InterpreterError::NoInstance {
+                    source_code: sources.named(file),
+                    operator,
+                    expected: "an instance of Num".to_string(),
+                    this_operation: span.into(),
+                    this_operand: value.span.into(),
+                })
+            }
+        },
+    };
+    Ok(Spanned::new(file, span, inner))
+}
+
+/// The method `name` of the instance for the type of `value`, if it's of a user declared
+/// type that has one.
+fn method<'a>(state: &'a InterpreterState, value: &Primary, name: &str) -> Option<&'a Primary> {
+    let data = match value {
+        Primary::Record { name, .. } => *name,
+        Primary::Constructor { data, arity, args, .. } if args.len() == *arity => *data,
+        _ => return None,
+    };
+    state.instances.get(&(data, name.to_string()))
+}
+
+/// Applies `function` to every argument in turn.
+fn call(
+    function: &Primary,
+    args: Vec<Spanned<Primary>>,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let function = Spanned::new(file, span.clone(), function.clone());
+    args.into_iter().try_fold(function, |function, argument| {
+        apply(function, argument, span.clone(), file, sources, state)
+    })
+}
+
+/// Applies `function` to `argument`, evaluating builtins once they have all their arguments.
+fn apply(
+    function: Spanned<Primary>,
+    argument: Spanned<Primary>,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    match function.inner {
+        Primary::Closure { param, body, mut captures } => {
+            let (fuel, depth) = (state.fuel.get(), state.calls.borrow().len());
+            // The stack beyond what the calls are allowed is left for evaluating the last one
+            // and reporting the error.
+            let stack_used = state.stack_base.abs_diff(stack_address());
+            let out_of_stack = stack_used > state.limits.depth * STACK_PER_CALL;
+            if fuel == 0 || depth >= state.limits.depth || out_of_stack {
+                let reason = match fuel {
+                    0 => format!("{} function calls", state.limits.fuel),
+                    _ => format!("{depth} nested function calls"),
+                };
+                return Err(recursion_limit(reason, span, file, sources, state));
+            }
+            state.fuel.set(fuel - 1);
+            if !match_pattern(&param.inner, &argument.inner, &mut captures) {
+                return Err(InterpreterError::ParamPatternMismatch {
+                    source_code: sources.named(param.file),
+                    value: argument.inner.display(&state.ident_map).to_string(),
+                    this_param: param.span.into(),
+                });
+            }
+            state.calls.borrow_mut().push((file, span.clone()));
+            let result = interpret_expression(*body, sources, state, &captures);
+            state.calls.borrow_mut().pop();
+            Ok(Spanned::new(file, span, result?.inner))
+        }
+        Primary::Builtin { builtin, mut args } => {
+            args.push(argument);
+            if args.len() < builtin.arity() {
+                return Ok(Spanned::new(file, span, Primary::Builtin { builtin, args }));
+            }
+            call_builtin(builtin, args, span, file, sources, state)
+        }
+        Primary::Constructor { data, constructor, arity, mut args } if args.len() < arity => {
+            args.push(argument.inner);
+            Ok(Spanned::new(file, span, Primary::Constructor { data, constructor, arity, args }))
+        }
+        _ => Err(InterpreterError::NotAFunction {
+            source_code: sources.named(file),
+            this_application: span.into(),
+            this_function: function.span.into(),
+        }),
+    }
+}
+
+/// Reports that a call at `span` would have gone over the limits, along with the calls that
+/// are still being evaluated. Recursive calls are only shown once.
+fn recursion_limit(
+    reason: String,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> InterpreterError {
+    let mut call_sites: Vec<((FileId, Range<usize>), usize)> = Vec::new();
+    for call in state.calls.borrow().iter().rev() {
+        match call_sites.iter_mut().find(|(site, _)| site == call) {
+            Some((_, calls)) => *calls += 1,
+            None => call_sites.push((call.clone(), 1)),
+        }
+    }
+    InterpreterError::RecursionLimit {
+        source_code: sources.named(file),
+        reason,
+        this_call: span.into(),
+        call_chain: call_sites
+            .into_iter()
+            .map(|((file, span), calls)| CallSite {
+                source_code: sources.named(file),
+                calls,
+                call: span.into(),
             })
+            .collect(),
+    }
+}
+
+fn call_builtin(
+    builtin: Builtin,
+    mut args: Vec<Spanned<Primary>>,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let wrong_argument = |argument: &Spanned<Primary>, expected: &str| {
+        InterpreterError::BuiltinWrongArgument {
+            source_code: sources.named(file),
+            builtin: builtin.name().to_string(),
+            expected: expected.to_string(),
+            this_argument: argument.span.clone().into(),
         }
-        Expression::FunctionApplication { function, argument } => {
-            let function = interpret_expression(*function, source)?;
-            let argument = interpret_expression(*argument, source)?;
+    };
+    let float = |argument: &Spanned<Primary>| match argument.inner {
+        Primary::Int(number) => Ok(number as f64),
+        Primary::Float(number) => Ok(number),
+        _ => Err(wrong_argument(argument, "a Float")),
+    };
+    let array = |argument: &Spanned<Primary>| match &argument.inner {
+        Primary::Array(elements) => Ok(elements.clone()),
+        _ => Err(wrong_argument(argument, "an array")),
+    };
 
-            todo!()
+    let inner = match builtin {
+        Builtin::Length => Primary::Int(array(&args[0])?.len() as i64),
+        Builtin::Map => {
+            let elements = array(&args[1])?;
+            let array_span = args[1].span.clone();
+            let function = args.swap_remove(0);
+            Primary::Array(
+                elements
+                    .into_iter()
+                    .map(|element| {
+                        let element = Spanned::new(file, array_span.clone(), element);
+                        let function = function.clone();
+                        Ok(apply(function, element, span.clone(), file, sources, state)?.inner)
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
         }
-        Expression::Unary { operator, rhs } => {
-            let rhs = interpret_expression(*rhs, source)?;
-
-            Ok(match operator {
-                UnaryOperator::Negative => match rhs.inner {
-                    Primary::Int(rhs) => Spanned {
-                        inner: Primary::Int(-rhs),
-                        span: expression.span.into(),
-                    },
-                    _ => todo!(),
-                },
-                UnaryOperator::Not => match rhs.inner {
-                    Primary::Bool(rhs) => Spanned {
-                        inner: Primary::Bool(!rhs),
-                        span: expression.span.into(),
-                    },
-                    _ => todo!(),
-                },
+        Builtin::Fold => {
+            let elements = array(&args[2])?;
+            let array_span = args[2].span.clone();
+            let mut args = args.into_iter();
+            let function = args.next().unwrap();
+            let mut accumulator = args.next().unwrap();
+            for element in elements {
+                let element = Spanned::new(file, array_span.clone(), element);
+                let function = function.clone();
+                let partial = apply(function, accumulator, span.clone(), file, sources, state)?;
+                accumulator = apply(partial, element, span.clone(), file, sources, state)?;
+            }
+            accumulator.inner
+        }
+        Builtin::Rgb | Builtin::Hsv | Builtin::Cmy | Builtin::Xyz => {
+            let (a, b, c) = (float(&args[0])?, float(&args[1])?, float(&args[2])?);
+            Primary::Color(match builtin {
+                Builtin::Rgb => Xyza::from_color(Srgba::new(a, b, c, 1.0)),
+                Builtin::Hsv => Xyza::from_color(Hsva::<Srgb, f64>::new(a, b, c, 1.0)),
+                Builtin::Cmy => Xyza::from_color(Srgba::new(1.0 - a, 1.0 - b, 1.0 - c, 1.0)),
+                _ => Xyza::new(a, b, c, 1.0),
             })
         }
-        Expression::Variable(identifier) => todo!(),
-        Expression::Primary(primary) => Ok(primary),
+        Builtin::Alpha => {
+            let alpha = float(&args[0])?;
+            let Primary::Color(color) = args[1].inner else {
+                return Err(wrong_argument(&args[1], "a color"));
+            };
+            Primary::Color(Xyza { alpha, ..color })
+        }
+        Builtin::Mix => {
+            let amount = float(&args[0])?;
+            let mut args = args.into_iter().skip(1);
+            let (mut from, mut to) = (args.next().unwrap(), args.next().unwrap());
+            let from_value = promote(from.inner, &to.inner);
+            to.inner = promote(to.inner, &from_value);
+            from.inner = from_value;
+            let lerp = |from: f64, to: f64| from + amount * (to - from);
+            match (&from.inner, &to.inner) {
+                (Primary::Int(from), Primary::Int(to)) => {
+                    Primary::Int(lerp(*from as f64, *to as f64).round() as i64)
+                }
+                (Primary::Float(from), Primary::Float(to)) => Primary::Float(lerp(*from, *to)),
+                (Primary::Color(from), Primary::Color(to)) => Primary::Color(Xyza::new(
+                    lerp(from.x, to.x),
+                    lerp(from.y, to.y),
+                    lerp(from.z, to.z),
+                    lerp(from.alpha, to.alpha),
+                )),
+                (value, _) => {
+                    let Some(function) = method(state, value, "mix") else {
+                        return Err(wrong_argument(&from, "an instance of Mix"));
+                    };
+                    let amount = Spanned::new(file, span.clone(), Primary::Float(amount));
+                    return call(function, vec![amount, from, to], span, file, sources, state);
+                }
+            }
+        }
+        Builtin::Negate => {
+            let value = args.swap_remove(0);
+            return negate(value, builtin.name(), span, file, sources, state);
+        }
+        Builtin::ToFloat => match args[0].inner {
+            Primary::Int(number) => Primary::Float(number as f64),
+            _ => return Err(wrong_argument(&args[0], "an Int")),
+        },
+        Builtin::Floor => Primary::Int(float(&args[0])?.floor() as i64),
+        Builtin::Round => Primary::Int(float(&args[0])?.round() as i64),
+        Builtin::Otherwise => Primary::Bool(true),
+    };
+    Ok(Spanned::new(file, span, inner))
+}
+
+/// Evaluates `array !! index`.
+fn index(
+    array: Spanned<Primary>,
+    index: Spanned<Primary>,
+    span: Range<usize>,
+    file: FileId,
+    sources: &SourceDb,
+    state: &InterpreterState,
+) -> Result<Spanned<Primary>, InterpreterError> {
+    let (Primary::Array(elements), Primary::Int(position)) = (&array.inner, &index.inner) else {
+        return Err(InterpreterError::IndexWrongType {
+            source_code: sources.named(file),
+            this_array: array.span.into(),
+            this_index: index.span.into(),
+        });
+    };
+    let element = usize::try_from(*position).ok().and_then(|position| elements.get(position));
+    match element {
+        Some(element) => Ok(Spanned::new(file, span, element.clone())),
+        None => Err(InterpreterError::IndexOutOfBounds {
+            source_code: sources.named(file),
+            index: index.inner.display(&state.ident_map).to_string(),
+            length: elements.len(),
+            this_array: array.span.into(),
+            this_index: index.span.into(),
+        }),
+    }
+}
+
+/// Checks whether `value` matches `pattern`, adding anything it binds to `scope`.
+fn match_pattern(pattern: &Pattern, value: &Primary, scope: &mut Scope) -> bool {
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Variable(ident) => {
+            scope.insert(*ident, value.clone());
+            true
+        }
+        Pattern::Literal(literal) => literal == value,
+        Pattern::Tuple(patterns) => match value {
+            Primary::Tuple(elements) if elements.len() == patterns.len() => patterns
+                .iter()
+                .zip(elements)
+                .all(|(pattern, element)| match_pattern(&pattern.inner, element, scope)),
+            _ => false,
+        },
+        Pattern::Array(patterns) => match value {
+            Primary::Array(elements) if elements.len() == patterns.len() => patterns
+                .iter()
+                .zip(elements)
+                .all(|(pattern, element)| match_pattern(&pattern.inner, element, scope)),
+            _ => false,
+        },
+        Pattern::Constructor { constructor, args: patterns } => match value {
+            Primary::Constructor { constructor: other, args, .. } if other == constructor => {
+                patterns
+                    .iter()
+                    .zip(args)
+                    .all(|(pattern, arg)| match_pattern(&pattern.inner, arg, scope))
+            }
+            _ => false,
+        },
     }
 }
 
@@ -220,7 +995,7 @@ pub enum InterpreterError {
     )]
     IfConditionWrongType {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
         #[label("In this if")]
         this_if: SourceSpan,
         #[label("This condition")]
@@ -233,7 +1008,7 @@ pub enum InterpreterError {
     )]
     BinaryOperandMismatch {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
         #[label("In this binary operation")]
         this_binary: SourceSpan,
         #[label("This operand")]
@@ -248,10 +1023,230 @@ pub enum InterpreterError {
     )]
     NegatedWrongType {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
         #[label("In this negate operation")]
         this_op: SourceSpan,
         #[label("This isn't a boolean")]
         this_expr: SourceSpan,
     },
+    #[error("'{operator}' is not defined for this value")]
+    #[diagnostic(
+        code(easl::interpreter::no_instance),
+        help = "User declared types need an instance of the operator's class to support it"
+    )]
+    NoInstance {
+        #[source_code]
+        source_code: NamedSource,
+        operator: &'static str,
+        expected: String,
+        #[label("In this operation")]
+        this_operation: SourceSpan,
+        #[label("Expected {expected}")]
+        this_operand: SourceSpan,
+    },
+    #[error("Division by zero")]
+    #[diagnostic(
+        code(easl::interpreter::division_by_zero),
+        help = "Integer division and remainder need a non-zero divisor"
+    )]
+    DivisionByZero {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This is zero")]
+        this_divisor: SourceSpan,
+    },
+    #[error("Attempted to call something that isn't a function")]
+    #[diagnostic(
+        code(easl::interpreter::not_a_function),
+        help = "Only lambdas and functions can be applied to arguments"
+    )]
+    NotAFunction {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("In this application")]
+        this_application: SourceSpan,
+        #[label("This isn't a function")]
+        this_function: SourceSpan,
+    },
+    #[error("'{ident}' has no value")]
+    #[diagnostic(
+        code(easl::interpreter::unbound_identifier),
+        help = "Make sure it is defined before it is used"
+    )]
+    UnboundIdentifier {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Used here")]
+        this_variable: SourceSpan,
+    },
+    #[error("'{ident}' is needed to work out its own value")]
+    #[diagnostic(
+        code(easl::interpreter::cyclic_definition),
+        help = "Only functions can refer to themselves, values are worked out right away"
+    )]
+    CyclicDefinition {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Used here before it has a value")]
+        this_variable: SourceSpan,
+    },
+    #[error("Guard did not evaluate to a boolean")]
+    #[diagnostic(
+        code(easl::interpreter::guard_not_bool),
+        help = "Make sure your guard evaluates to a boolean"
+    )]
+    GuardWrongType {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This guard")]
+        this_guard: SourceSpan,
+    },
+    #[error("Non-exhaustive patterns in case expression or function")]
+    #[diagnostic(
+        code(easl::interpreter::non_exhaustive_case),
+        help = "Add a wildcard `_` arm or clause to handle every other value"
+    )]
+    NonExhaustiveCase {
+        #[source_code]
+        source_code: NamedSource,
+        value: String,
+        #[label("No pattern matched {value}")]
+        this_scrutinee: SourceSpan,
+    },
+    #[error("Wrong argument passed to '{builtin}'")]
+    #[diagnostic(
+        code(easl::interpreter::builtin_wrong_argument),
+        help = "Check the builtin's type"
+    )]
+    BuiltinWrongArgument {
+        #[source_code]
+        source_code: NamedSource,
+        builtin: String,
+        expected: String,
+        #[label("Expected {expected}")]
+        this_argument: SourceSpan,
+    },
+    #[error("Only arrays can be indexed, and only by integers")]
+    #[diagnostic(code(easl::interpreter::index_wrong_type))]
+    IndexWrongType {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This should be an array")]
+        this_array: SourceSpan,
+        #[label("This should be an integer")]
+        this_index: SourceSpan,
+    },
+    #[error("Index out of bounds")]
+    #[diagnostic(
+        code(easl::interpreter::index_out_of_bounds),
+        help = "Indices start at 0, so the last element is at `length array - 1`"
+    )]
+    IndexOutOfBounds {
+        #[source_code]
+        source_code: NamedSource,
+        index: String,
+        length: usize,
+        #[label("This array has {length} elements")]
+        this_array: SourceSpan,
+        #[label("But the index is {index}")]
+        this_index: SourceSpan,
+    },
+    #[error("Range bounds must be integers")]
+    #[diagnostic(code(easl::interpreter::range_bound_wrong_type))]
+    RangeBoundWrongType {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This isn't an integer")]
+        this_bound: SourceSpan,
+    },
+    #[error("Range of {length} elements is too long to build")]
+    #[diagnostic(
+        code(easl::interpreter::range_too_long),
+        help = "Each element takes as much fuel as a function call, raise the limit with --fuel"
+    )]
+    RangeTooLong {
+        #[source_code]
+        source_code: NamedSource,
+        length: String,
+        fuel: u64,
+        #[label("Only {fuel} elements or calls are left")]
+        this_range: SourceSpan,
+    },
+    #[error("Pattern did not match {value}")]
+    #[diagnostic(
+        code(easl::interpreter::let_pattern_mismatch),
+        help = "Use a case expression if the value might not match"
+    )]
+    LetPatternMismatch {
+        #[source_code]
+        source_code: NamedSource,
+        value: String,
+        #[label("This pattern")]
+        this_pattern: SourceSpan,
+        #[label("Doesn't match this value")]
+        this_value: SourceSpan,
+    },
+    #[error("Gave up evaluating after {reason}")]
+    #[diagnostic(
+        code(easl::interpreter::recursion_limit),
+        help = "Make sure recursive functions always get closer to a case that doesn't \
+                recurse, or raise the limits with --fuel and --max-depth"
+    )]
+    RecursionLimit {
+        #[source_code]
+        source_code: NamedSource,
+        reason: String,
+        #[label("This call would go over the limit")]
+        this_call: SourceSpan,
+        #[related]
+        call_chain: Vec<CallSite>,
+    },
+    #[error("Argument {value} did not match the parameter's pattern")]
+    #[diagnostic(
+        code(easl::interpreter::param_pattern_mismatch),
+        help = "Use a case expression if the argument might not match"
+    )]
+    ParamPatternMismatch {
+        #[source_code]
+        source_code: NamedSource,
+        value: String,
+        #[label("This parameter")]
+        this_param: SourceSpan,
+    },
+    #[error("{value} has no field '{field}'")]
+    #[diagnostic(code(easl::interpreter::no_such_field))]
+    NoSuchField {
+        #[source_code]
+        source_code: NamedSource,
+        field: String,
+        value: String,
+        #[label("This value")]
+        this_record: SourceSpan,
+        #[label("This field")]
+        this_field: SourceSpan,
+    },
+    #[error("Couldn't reserve {size} bytes of stack to evaluate the program with")]
+    #[diagnostic(
+        code(easl::interpreter::no_stack),
+        help = "Lower the depth limit, every nested call it allows needs more stack"
+    )]
+    NoStack {
+        size: usize,
+        #[source]
+        error: std::io::Error,
+    },
+}
+
+/// A function call that was still being evaluated when a `RecursionLimit` was reached.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Called from here")]
+#[diagnostic(severity(Advice))]
+pub struct CallSite {
+    #[source_code]
+    source_code: NamedSource,
+    calls: usize,
+    #[label("{calls} unfinished call(s) made here")]
+    call: SourceSpan,
 }
@@ -1,4 +1,8 @@
 pub mod compiler;
+pub mod formatter;
 pub mod interpreter;
+pub mod modules;
 pub mod parser;
+pub mod source;
+pub mod typeck;
 pub mod utils;
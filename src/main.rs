@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{builder::RangedU64ValueParser, Parser, Subcommand};
+use easl::interpreter::Limits;
+use easl::source::{FileId, Source, SourceDb};
 use miette::{ErrReport, Result};
 #[derive(Parser, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -11,42 +13,126 @@ pub struct Args {
 
 #[derive(Subcommand, Clone)]
 pub enum Commands {
-    Run { source_file: PathBuf },
+    Run {
+        source_file: PathBuf,
+        /// Directories to look for included and imported files in
+        #[arg(short = 'I', long)]
+        include_path: Vec<PathBuf>,
+        /// Show what type aliases stand for in type errors
+        #[arg(long)]
+        expand_aliases: bool,
+        /// How many functions evaluating a single definition can call, ranges take one per element
+        #[arg(long, default_value_t = Limits::default().fuel)]
+        fuel: u64,
+        /// How many function calls can be evaluated inside each other, at most 16384
+        #[arg(
+            long,
+            default_value_t = Limits::default().depth,
+            value_parser = RangedU64ValueParser::<usize>::new().range(..=Limits::MAX_DEPTH as u64),
+        )]
+        max_depth: usize,
+    },
+    /// Prints the inferred type of every top level definition
+    Types {
+        source_file: PathBuf,
+        /// Directories to look for included and imported files in
+        #[arg(short = 'I', long)]
+        include_path: Vec<PathBuf>,
+        /// Show what type aliases stand for
+        #[arg(long)]
+        expand_aliases: bool,
+    },
+    /// Formats a source file in place
+    Fmt {
+        source_file: PathBuf,
+        /// Only check whether the file is formatted, without changing it
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     match args.command {
-        Commands::Run { source_file } => {
-            let Ok(source) = std::fs::read_to_string(source_file) else {
+        Commands::Run { source_file, include_path, expand_aliases, fuel, max_depth } => {
+            let mut sources = SourceDb::new();
+            let mut program = easl::modules::load(&source_file, &include_path, &mut sources)
+                .map_err(|error| sources.report(error))?;
+            for warning in program.warnings.drain(..) {
+                eprintln!("{:?}", sources.report(warning));
+            }
+            let ident_map = &mut program.ident_map;
+            let typed =
+                easl::typeck::infer(&program.statements, ident_map, &sources, expand_aliases)
+                    .map_err(|error| sources.report(error))?;
+            println!("{:#?}", program.statements);
+
+            let limits = Limits { fuel, depth: max_depth };
+            easl::interpreter::interpret(
+                program.statements,
+                &sources,
+                program.ident_map,
+                typed.expressions,
+                limits,
+            )
+            .map_err(|error| sources.report(error))?;
+        }
+        Commands::Types { source_file, include_path, expand_aliases } => {
+            let mut sources = SourceDb::new();
+            let mut program = easl::modules::load(&source_file, &include_path, &mut sources)
+                .map_err(|error| sources.report(error))?;
+            for warning in program.warnings.drain(..) {
+                eprintln!("{:?}", sources.report(warning));
+            }
+            let typed = easl::typeck::infer(
+                &program.statements,
+                &mut program.ident_map,
+                &sources,
+                expand_aliases,
+            )
+            .map_err(|error| sources.report(error))?;
+            for (ident, type_) in typed.definitions {
+                let name = program.ident_map.get(&ident).map_or("", String::as_str);
+                let type_ = type_.display(&program.ident_map, expand_aliases);
+                println!("{} :: {type_}", signature_name(name));
+            }
+        }
+        Commands::Fmt { source_file, check } => {
+            let Ok(text) = std::fs::read_to_string(&source_file) else {
                 return Err(miette::miette!("Could not read source file"));
             };
 
-            let (mut statements, mut ident_map) = easl::parser::parse(&source)
-                .map_err(<easl::parser::ParserError as Into<ErrReport>>::into)?;
-            println!("{:#?}", statements);
-
-
-            // Complicated include logic. I would love to simplify this.
-            let includes = statements.clone().into_iter().filter_map(|statement| match statement {
-                easl::parser::ast::Statement::Include { source } => Some(source),
-                _ => None,
-            }).map(|source| {
-                easl::parser::parse(&source)
-                    .map_err(<easl::parser::ParserError as Into<ErrReport>>::into)
-            });
-
-            for include in includes {
-                let (mut include_statements, include_ident_map) = include?;
-                statements.append(&mut include_statements);
-                ident_map.map.extend(include_ident_map.map);
+            let name = source_file.display().to_string();
+            let source = Source { file: FileId::default(), name: &name, text: &text };
+            let formatted = easl::formatter::format(source)
+                .map_err(<easl::parser::ParserErrors as Into<ErrReport>>::into)?;
+            if formatted == text {
+                return Ok(());
+            }
+            if check {
+                return Err(miette::miette!("{} is not formatted", source_file.display()));
+            }
+            if std::fs::write(&source_file, formatted).is_err() {
+                return Err(miette::miette!("Could not write source file"));
             }
-
-            easl::interpreter::interpret(statements, &source, ident_map)
-                .map_err(<easl::interpreter::InterpreterError as Into<ErrReport>>::into)?;
         }
     }
 
     Ok(())
 }
+
+/// `name` the way it's written in a type signature, operators in parentheses.
+fn signature_name(name: &str) -> String {
+    // Names in modules are qualified, like `Palettes.sunset` or `Palettes.<+>`.
+    let unqualified = match name.split_once('.') {
+        Some((module, unqualified)) if module.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            unqualified
+        }
+        _ => name,
+    };
+    match unqualified.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        true => name.to_string(),
+        false => format!("({name})"),
+    }
+}
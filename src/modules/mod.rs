@@ -0,0 +1,233 @@
+//! Loading a program spread over several files.
+//!
+//! `include "path.easl"` makes every definition of the file visible. Paths are relative to
+//! the including file, falling back to the search path. `import Palettes` loads
+//! `Palettes.easl` the same way and makes its definitions visible both as `sunset` and as
+//! `Palettes.sunset`, while `import Palettes (sunset)` only brings `sunset` in unqualified.
+//! Definitions are qualified with the name of their file, so a file can't depend on two
+//! different files with the same name.
+
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::{
+    parser::{
+        self,
+        ast::{IdentifierMap, Statement},
+        Dependency, Exports, ParserErrors, ParserWarning,
+    },
+    source::{FileId, SourceDb},
+};
+
+/// Every file of a program, parsed.
+pub struct Program {
+    /// The statements of all files, those of a file's dependencies coming before its own.
+    pub statements: Vec<Statement>,
+    pub ident_map: IdentifierMap,
+    pub warnings: Vec<ParserWarning>,
+}
+
+/// Loads the program in `entry` and everything it includes or imports, adding every file
+/// to `sources`.
+pub fn load(
+    entry: &Path,
+    search_path: &[PathBuf],
+    sources: &mut SourceDb,
+) -> Result<Program, ModuleError> {
+    let text = read(entry)?;
+    let file = sources.add(entry.display().to_string(), text, None);
+    let mut loader = Loader {
+        search_path,
+        sources,
+        loaded: HashMap::new(),
+        stack: Vec::new(),
+        statements: Vec::new(),
+        ident_map: IdentifierMap::new(),
+        warnings: Vec::new(),
+    };
+    let entry = entry.canonicalize().unwrap_or_else(|_| entry.to_path_buf());
+    loader.load(&entry, file, None)?;
+    Ok(Program {
+        statements: loader.statements,
+        ident_map: loader.ident_map,
+        warnings: loader.warnings,
+    })
+}
+
+struct Loader<'a> {
+    search_path: &'a [PathBuf],
+    sources: &'a mut SourceDb,
+    /// The exports of the files that have been loaded so far, by their canonical path.
+    loaded: HashMap<PathBuf, Exports>,
+    /// The files currently being loaded, each one depended on by the one before it.
+    stack: Vec<PathBuf>,
+    statements: Vec<Statement>,
+    ident_map: IdentifierMap,
+    warnings: Vec<ParserWarning>,
+}
+
+impl Loader<'_> {
+    fn load(
+        &mut self,
+        path: &Path,
+        file: FileId,
+        module: Option<&str>,
+    ) -> Result<Exports, ModuleError> {
+        self.stack.push(path.to_path_buf());
+
+        let mut dependencies = HashMap::new();
+        // The files whose definitions are qualified with each module name, definitions of
+        // different files with the same qualified name couldn't be told apart.
+        let mut modules: HashMap<String, (PathBuf, Option<Range<usize>>)> = HashMap::new();
+        if let Some(module) = module {
+            modules.insert(module.to_string(), (path.to_path_buf(), None));
+        }
+        for dependency in parser::dependencies(self.sources.source(file)) {
+            let Some(resolved) = self.resolve(path, &dependency.inner) else {
+                return Err(ModuleError::NotFound {
+                    source_code: self.sources.named(file),
+                    name: match dependency.inner {
+                        Dependency::Include(path) => path,
+                        Dependency::Import(module) => format!("{module}.easl"),
+                    },
+                    dependency: dependency.span.into(),
+                });
+            };
+            if let Some(start) = self.stack.iter().position(|path| path == &resolved) {
+                let cycle = self.stack[start..]
+                    .iter()
+                    .chain([&resolved])
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(ModuleError::Cycle {
+                    source_code: self.sources.named(file),
+                    cycle,
+                    dependency: dependency.span.into(),
+                });
+            }
+            let qualifier = module_name(&resolved);
+            match modules.get(&qualifier) {
+                Some((other, previous)) if other != &resolved => {
+                    return Err(ModuleError::NameClash {
+                        source_code: self.sources.named(file),
+                        module: qualifier,
+                        dependency: dependency.span.into(),
+                        previous: previous.clone().map(Into::into),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    let span = Some(dependency.span.clone());
+                    modules.insert(qualifier.clone(), (resolved.clone(), span));
+                }
+            }
+
+            let exports = match self.loaded.get(&resolved) {
+                Some(exports) => exports.clone(),
+                None => {
+                    let text = read(&resolved)?;
+                    let name = resolved.display().to_string();
+                    let included_from = Some((file, dependency.span.clone()));
+                    let dependency_file = self.sources.add(name, text, included_from);
+                    self.load(&resolved, dependency_file, Some(&qualifier))?
+                }
+            };
+            dependencies.insert(dependency.inner, exports);
+        }
+
+        let source = self.sources.source(file);
+        let (parsed, errors) = parser::parse_module(source, module, &dependencies);
+        if !errors.is_empty() {
+            return Err(ParserErrors { errors }.into());
+        }
+        self.statements.extend(parsed.statements);
+        self.ident_map.map.extend(parsed.ident_map.map);
+        self.warnings.extend(parsed.warnings);
+
+        self.stack.pop();
+        self.loaded.insert(path.to_path_buf(), parsed.exports.clone());
+        Ok(parsed.exports)
+    }
+
+    /// Finds the file `dependency` refers to, next to `from` or in the search path.
+    fn resolve(&self, from: &Path, dependency: &Dependency) -> Option<PathBuf> {
+        let relative = match dependency {
+            Dependency::Include(path) => PathBuf::from(path),
+            Dependency::Import(module) => PathBuf::from(format!("{module}.easl")),
+        };
+        from.parent()
+            .into_iter()
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(&relative))
+            .find(|candidate| candidate.is_file())
+            .and_then(|candidate| candidate.canonicalize().ok())
+    }
+}
+
+/// The name definitions of the file at `path` are qualified with.
+fn module_name(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn read(path: &Path) -> Result<String, ModuleError> {
+    std::fs::read_to_string(path).map_err(|_| ModuleError::Unreadable {
+        path: path.display().to_string(),
+    })
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ModuleError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ParserErrors(#[from] ParserErrors),
+    #[error("Could not read source file '{path}'")]
+    #[diagnostic(code(easl::modules::unreadable))]
+    Unreadable { path: String },
+    #[error("Could not find '{name}'")]
+    #[diagnostic(
+        code(easl::modules::not_found),
+        help = "Paths are relative to the including file, or to a directory passed with \
+                --include-path"
+    )]
+    NotFound {
+        #[source_code]
+        source_code: NamedSource,
+        name: String,
+        #[label("Included here")]
+        dependency: SourceSpan,
+    },
+    #[error("Two different modules are named '{module}'")]
+    #[diagnostic(
+        code(easl::modules::name_clash),
+        help = "The definitions of both would be called '{module}.name', rename one of the files"
+    )]
+    NameClash {
+        #[source_code]
+        source_code: NamedSource,
+        module: String,
+        #[label("This one")]
+        dependency: SourceSpan,
+        /// The other dependency, unless it's the file itself.
+        #[label("Clashes with this one")]
+        previous: Option<SourceSpan>,
+    },
+    #[error("Include cycle: {cycle}")]
+    #[diagnostic(
+        code(easl::modules::cycle),
+        help = "Move the definitions both files need into a separate file"
+    )]
+    Cycle {
+        #[source_code]
+        source_code: NamedSource,
+        cycle: String,
+        #[label("This closes the cycle")]
+        dependency: SourceSpan,
+    },
+}
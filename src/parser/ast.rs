@@ -1,19 +1,37 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
     ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
-use rusttyc::{Variant, Arity};
+use palette::FromColor;
+
+use crate::source::FileId;
 
 pub struct Spanned<T> {
     pub span: Range<usize>,
+    /// The file `span` is in.
+    pub file: FileId,
+    pub id: NodeId,
     pub inner: T,
 }
 
 impl<T> Spanned<T> {
-    pub fn new(span: Range<usize>, inner: T) -> Self {
-        Self { span, inner }
+    pub fn new(file: FileId, span: Range<usize>, inner: T) -> Self {
+        Self { span, file, id: NodeId::fresh(), inner }
+    }
+}
+
+/// Tells nodes apart, even ones with the same span, like the nodes a function with several
+/// clauses is desugared into. Clones of a node keep its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    fn fresh() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
     }
 }
 
@@ -24,6 +42,8 @@ where
     fn clone(&self) -> Self {
         Self {
             span: self.span.clone(),
+            file: self.file,
+            id: self.id,
             inner: self.inner.clone(),
         }
     }
@@ -50,17 +70,55 @@ pub enum Statement {
     //TODO: Syntax for patern matching, in place type ascription, and parameters
     Assignment {
         ident: Identifier,
-        // args: Vec<Node>,
+        /// The parameters written before the `=`. These are already desugared into
+        /// nested lambdas in `expr` and are only kept around for diagnostics.
+        params: Vec<Spanned<Pattern>>,
         expr: Spanned<Expression>,
         // type_: Option<Type>,
     },
     TypeAscription {
         ident: Identifier,
-        type_: Type,
+        type_: Spanned<Type>,
+    },
+    /// `infixl 6 <+>, <->`
+    Fixity {
+        fixity: Fixity,
+        operators: Vec<Spanned<Identifier>>,
+    },
+    /// `record Name = { field :: Type, ... }`
+    Record {
+        ident: Identifier,
+        fields: Vec<(Spanned<Identifier>, Spanned<Type>)>,
+    },
+    /// `data Name = Constructor Type ... | ...`, also used for
+    /// `newtype Name = Constructor Type`, which is a data type with a single constructor.
+    Data {
+        ident: Identifier,
+        constructors: Vec<(Spanned<Identifier>, Vec<Spanned<Type>>)>,
+    },
+    /// `type Name = Type`
+    TypeAlias {
+        ident: Identifier,
+        type_: Spanned<Type>,
+    },
+    /// `instance Class Type where method = ...; ...`
+    Instance {
+        class: Spanned<Class>,
+        type_: Spanned<Identifier>,
+        /// Every method of the class, by name.
+        methods: Vec<(Spanned<Identifier>, Spanned<Expression>)>,
     },
     Include {
         source: String,
     },
+    /// `import Module` or `import Module (a, b)`
+    Import {
+        module: String,
+        /// The definitions brought into scope unqualified, all of them if `None`.
+        names: Option<Vec<Spanned<Identifier>>>,
+    },
+    /// A statement that failed to parse or build, its error has already been reported.
+    Error,
     EOI,
 }
 
@@ -85,8 +143,278 @@ pub enum Expression {
         function: Box<Spanned<Expression>>,
         argument: Box<Spanned<Expression>>,
     },
+    /// `let a = x; b = y in body`, also used for `where` clauses.
+    /// Each binding can see the ones before it.
+    Let {
+        bindings: Vec<(Spanned<Pattern>, Spanned<Expression>)>,
+        body: Box<Spanned<Expression>>,
+    },
+    /// `case scrutinee of arms`, evaluating to the body of the first arm whose pattern matches
+    /// and one of whose guards holds.
+    Case {
+        scrutinee: Box<Spanned<Expression>>,
+        arms: Vec<CaseArm>,
+    },
+    /// `[a, b, c]`
+    Array(Vec<Spanned<Expression>>),
+    /// `(a, b)`
+    Tuple(Vec<Spanned<Expression>>),
+    /// `Name { field = value, ... }`, with the fields in declaration order.
+    Record {
+        name: Identifier,
+        fields: Vec<(Spanned<Identifier>, Spanned<Expression>)>,
+    },
+    /// `record.field`
+    FieldAccess {
+        record: Box<Spanned<Expression>>,
+        field: Spanned<Identifier>,
+    },
+    /// `[start..end]`, inclusive on both ends.
+    Range {
+        start: Box<Spanned<Expression>>,
+        end: Box<Spanned<Expression>>,
+    },
+    /// `(expr :: Type)`
+    Annotated {
+        expr: Box<Spanned<Expression>>,
+        type_: Spanned<Type>,
+    },
     Variable(Identifier),
     Primary(Spanned<Primary>),
+    /// An expression that failed to build, its error has already been reported.
+    Error,
+}
+
+/// `pattern | guard -> body | ...`, or a clause of a function defined by several, with its
+/// `where` clause.
+#[derive(Debug, Clone)]
+pub struct CaseArm {
+    pub pattern: Spanned<Pattern>,
+    /// Bound once the pattern matched, in scope for the guards and bodies, like `Let`'s.
+    pub bindings: Vec<(Spanned<Pattern>, Spanned<Expression>)>,
+    /// Tried in order. If no guard holds the next arm is tried.
+    pub alternatives: Vec<Alternative>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alternative {
+    /// Always holds if there is none.
+    pub guard: Option<Spanned<Expression>>,
+    pub body: Spanned<Expression>,
+}
+
+impl Expression {
+    pub fn is_lambda(&self) -> bool {
+        matches!(self, Expression::Primary(Spanned { inner: Primary::Lambda { .. }, .. }))
+    }
+
+    /// The variables the expression refers to that aren't bound inside of it, in other words
+    /// the top level definitions and builtins it uses.
+    pub fn free_variables(&self) -> HashSet<Identifier> {
+        let mut free = HashSet::new();
+        self.collect_free_variables(&mut Vec::new(), &mut free);
+        free
+    }
+
+    /// Adds the variables in the expression that aren't in `bound` to `free`.
+    fn collect_free_variables(&self, bound: &mut Vec<Identifier>, free: &mut HashSet<Identifier>) {
+        let mut visit = |expression: &Spanned<Expression>, bound: &mut Vec<Identifier>| {
+            expression.inner.collect_free_variables(bound, free)
+        };
+        match self {
+            Expression::If { cond, then, else_ } => {
+                for expression in [cond, then, else_] {
+                    visit(expression, bound);
+                }
+            }
+            Expression::Binary { lhs, rhs, .. }
+            | Expression::FunctionApplication { function: lhs, argument: rhs }
+            | Expression::Range { start: lhs, end: rhs } => {
+                visit(lhs, bound);
+                visit(rhs, bound);
+            }
+            Expression::Unary { rhs: expr, .. }
+            | Expression::FieldAccess { record: expr, .. }
+            | Expression::Annotated { expr, .. } => visit(expr, bound),
+            Expression::Array(elements) | Expression::Tuple(elements) => {
+                for element in elements {
+                    visit(element, bound);
+                }
+            }
+            Expression::Record { fields, .. } => {
+                for (_, value) in fields {
+                    visit(value, bound);
+                }
+            }
+            Expression::Let { bindings, body } => {
+                let outer = bound.len();
+                for (pattern, value) in bindings {
+                    visit(value, bound);
+                    pattern.inner.variables(bound);
+                }
+                visit(body, bound);
+                bound.truncate(outer);
+            }
+            Expression::Case { scrutinee, arms } => {
+                visit(scrutinee, bound);
+                for CaseArm { pattern, bindings, alternatives } in arms {
+                    let outer = bound.len();
+                    pattern.inner.variables(bound);
+                    for (pattern, value) in bindings {
+                        visit(value, bound);
+                        pattern.inner.variables(bound);
+                    }
+                    for Alternative { guard, body } in alternatives {
+                        for expression in guard.iter().chain([body]) {
+                            visit(expression, bound);
+                        }
+                    }
+                    bound.truncate(outer);
+                }
+            }
+            Expression::Variable(ident) => {
+                if !bound.contains(ident) {
+                    free.insert(*ident);
+                }
+            }
+            Expression::Primary(Spanned { inner: Primary::Lambda { param, body }, .. }) => {
+                let outer = bound.len();
+                param.inner.variables(bound);
+                visit(body, bound);
+                bound.truncate(outer);
+            }
+            Expression::Primary(_) | Expression::Error => {}
+        }
+    }
+}
+
+/// Orders definitions so that each one comes after the ones it uses, given the top level
+/// name every definition defines, if any, and the names it uses. Definitions that use each
+/// other, directly or through other definitions, are grouped together, and apart from that
+/// definitions stay in the order they are given in. The groups hold indices into
+/// `definitions`.
+pub fn dependency_order(
+    definitions: &[(Option<Identifier>, HashSet<Identifier>)],
+) -> Vec<Vec<usize>> {
+    // Tarjan's algorithm, which finds the groups in exactly this order.
+    struct Search<'a> {
+        definitions: &'a [(Option<Identifier>, HashSet<Identifier>)],
+        defined_by: HashMap<Identifier, usize>,
+        /// The order the definitions have been visited in.
+        visited: Vec<Option<usize>>,
+        visits: usize,
+        /// The definitions whose group hasn't been found yet.
+        stack: Vec<usize>,
+        groups: Vec<Vec<usize>>,
+    }
+
+    impl Search<'_> {
+        /// Visits `definition` and everything it uses, returning the earliest visited
+        /// definition still on the stack that it can reach.
+        fn visit(&mut self, definition: usize) -> usize {
+            let order = self.visits;
+            self.visits += 1;
+            self.visited[definition] = Some(order);
+            self.stack.push(definition);
+            let mut lowest = order;
+            for used in &self.definitions[definition].1 {
+                let Some(&used) = self.defined_by.get(used) else {
+                    continue;
+                };
+                match self.visited[used] {
+                    None => lowest = lowest.min(self.visit(used)),
+                    Some(used_order) if self.stack.contains(&used) => {
+                        lowest = lowest.min(used_order)
+                    }
+                    Some(_) => {}
+                }
+            }
+            if lowest == order {
+                let start = self.stack.iter().position(|on_stack| *on_stack == definition);
+                let mut group = self.stack.split_off(start.unwrap());
+                group.sort_unstable();
+                self.groups.push(group);
+            }
+            lowest
+        }
+    }
+
+    let defined_by = definitions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (defines, _))| Some(((*defines)?, index)))
+        .collect();
+    let mut search = Search {
+        definitions,
+        defined_by,
+        visited: vec![None; definitions.len()],
+        visits: 0,
+        stack: Vec::new(),
+        groups: Vec::new(),
+    };
+    for definition in 0..definitions.len() {
+        if search.visited[definition].is_none() {
+            search.visit(definition);
+        }
+    }
+    search.groups
+}
+
+impl Pattern {
+    /// Adds the variables the pattern binds to `variables`.
+    pub fn variables(&self, variables: &mut Vec<Identifier>) {
+        match self {
+            Pattern::Variable(ident) => variables.push(*ident),
+            Pattern::Tuple(patterns)
+            | Pattern::Array(patterns)
+            | Pattern::Constructor { args: patterns, .. } => {
+                for pattern in patterns {
+                    pattern.inner.variables(variables);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    /// Whether the pattern matches every value `other` matches.
+    pub fn subsumes(&self, other: &Pattern) -> bool {
+        let all = |patterns: &[Spanned<Pattern>], others: &[Spanned<Pattern>]| {
+            patterns.len() == others.len()
+                && patterns.iter().zip(others).all(|(pattern, other)| {
+                    pattern.inner.subsumes(&other.inner)
+                })
+        };
+        match (self, other) {
+            (Pattern::Wildcard | Pattern::Variable(_), _) => true,
+            (Pattern::Literal(literal), Pattern::Literal(other)) => literal == other,
+            (Pattern::Tuple(patterns), Pattern::Tuple(others)) => all(patterns, others),
+            (Pattern::Array(patterns), Pattern::Array(others)) => all(patterns, others),
+            (
+                Pattern::Constructor { constructor, args },
+                Pattern::Constructor { constructor: other, args: others },
+            ) => constructor == other && all(args, others),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`, matches anything without binding it.
+    Wildcard,
+    /// Matches anything and binds it to the identifier.
+    Variable(Identifier),
+    /// Matches values equal to the literal.
+    Literal(Primary),
+    /// `(a, b)`, matches tuples of the same length whose elements match.
+    Tuple(Vec<Spanned<Pattern>>),
+    /// `[a, b]`, matches arrays of the same length whose elements match.
+    Array(Vec<Spanned<Pattern>>),
+    /// `Constructor a b`, matches values built with the constructor whose arguments match.
+    Constructor {
+        constructor: Identifier,
+        args: Vec<Spanned<Pattern>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -104,6 +432,137 @@ pub enum BinaryOperator {
     Mul,
     Div,
     Remainder,
+
+    /// `.&.`, bitwise and of two `Int`s.
+    BitAnd,
+    /// `.|.`, bitwise or of two `Int`s.
+    BitOr,
+    /// `.^.`, bitwise exclusive or of two `Int`s.
+    BitXor,
+    /// `.<<.`, shifts an `Int` left by the number of bits on the right.
+    ShiftLeft,
+    /// `.>>.`, shifts an `Int` right by the number of bits on the right, keeping its sign.
+    ShiftRight,
+
+    /// `array !! index`
+    Index,
+}
+
+impl BinaryOperator {
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "==" => BinaryOperator::Equivalent,
+            "!=" => BinaryOperator::NotEquivalent,
+            ">" => BinaryOperator::GreaterThan,
+            "<" => BinaryOperator::LessThan,
+            ">=" => BinaryOperator::GreaterThanOrEqual,
+            "<=" => BinaryOperator::LessThanOrEqual,
+            "+" => BinaryOperator::Add,
+            "-" => BinaryOperator::Sub,
+            "*" => BinaryOperator::Mul,
+            "/" => BinaryOperator::Div,
+            "%" => BinaryOperator::Remainder,
+            ".&." => BinaryOperator::BitAnd,
+            ".|." => BinaryOperator::BitOr,
+            ".^." => BinaryOperator::BitXor,
+            ".<<." => BinaryOperator::ShiftLeft,
+            ".>>." => BinaryOperator::ShiftRight,
+            "!!" => BinaryOperator::Index,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Equivalent => "==",
+            BinaryOperator::NotEquivalent => "!=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::BitAnd => ".&.",
+            BinaryOperator::BitOr => ".|.",
+            BinaryOperator::BitXor => ".^.",
+            BinaryOperator::ShiftLeft => ".<<.",
+            BinaryOperator::ShiftRight => ".>>.",
+            BinaryOperator::Index => "!!",
+        }
+    }
+
+    /// The class both operands have to be an instance of. `%` and the bitwise operators only
+    /// work on `Int`s and `!!` on arrays.
+    pub fn class(&self) -> Option<Class> {
+        match self {
+            BinaryOperator::Equivalent | BinaryOperator::NotEquivalent => Some(Class::Eq),
+            BinaryOperator::GreaterThan
+            | BinaryOperator::LessThan
+            | BinaryOperator::GreaterThanOrEqual
+            | BinaryOperator::LessThanOrEqual => Some(Class::Ord),
+            BinaryOperator::Add => Some(Class::Add),
+            BinaryOperator::Sub | BinaryOperator::Mul | BinaryOperator::Div => Some(Class::Num),
+            BinaryOperator::Remainder
+            | BinaryOperator::BitAnd
+            | BinaryOperator::BitOr
+            | BinaryOperator::BitXor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight
+            | BinaryOperator::Index => None,
+        }
+    }
+
+    pub fn fixity(&self) -> Fixity {
+        let (associativity, precedence) = match self {
+            BinaryOperator::Equivalent
+            | BinaryOperator::NotEquivalent
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThan
+            | BinaryOperator::GreaterThanOrEqual
+            | BinaryOperator::LessThanOrEqual => (Associativity::None, 4),
+            BinaryOperator::Add | BinaryOperator::Sub => (Associativity::Left, 6),
+            BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::Remainder
+            | BinaryOperator::BitAnd => (Associativity::Left, 7),
+            BinaryOperator::BitXor => (Associativity::Left, 6),
+            BinaryOperator::BitOr => (Associativity::Left, 5),
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => (Associativity::Left, 8),
+            BinaryOperator::Index => (Associativity::Left, 9),
+        };
+        Fixity {
+            associativity,
+            precedence,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// `a == b == c` is an error rather than being grouped either way.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixity {
+    pub associativity: Associativity,
+    /// From 0 to 9, higher binds tighter.
+    pub precedence: u8,
+}
+
+impl Default for Fixity {
+    /// Operators without a fixity declaration are `infixl 9`.
+    fn default() -> Self {
+        Self {
+            associativity: Associativity::Left,
+            precedence: 9,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -121,8 +580,15 @@ impl Primary {
             (Primary::Bool(_), Primary::Bool(_)) => true,
             (Primary::Color(_), Primary::Color(_)) => true,
             (Primary::Int(_), Primary::Int(_)) => true,
+            (Primary::Float(_), Primary::Float(_)) => true,
             (Primary::String(_), Primary::String(_)) => true,
             (Primary::Lambda { .. }, Primary::Lambda { .. }) => false,
+            (Primary::Closure { .. }, Primary::Closure { .. }) => false,
+            (Primary::Builtin { .. }, Primary::Builtin { .. }) => false,
+            (Primary::Array(_), Primary::Array(_)) => true,
+            (Primary::Tuple(l), Primary::Tuple(r)) => l.len() == r.len(),
+            (Primary::Record { name: l, .. }, Primary::Record { name: r, .. }) => l == r,
+            (Primary::Constructor { data: l, .. }, Primary::Constructor { data: r, .. }) => l == r,
             (Primary::Unit, Primary::Unit) => true,
             _ => false,
         }
@@ -131,12 +597,43 @@ impl Primary {
 
 #[derive(Debug, Clone)]
 pub enum Primary {
+    /// A lambda takes a single argument that has to match `param`, lambdas with several
+    /// parameters are desugared into nested ones.
     Lambda {
-        param: Identifier,
+        param: Box<Spanned<Pattern>>,
+        body: Box<Spanned<Expression>>,
+    },
+    /// A lambda together with the values it captured from the scope it was evaluated in.
+    /// Only ever produced by the interpreter.
+    Closure {
+        param: Box<Spanned<Pattern>>,
         body: Box<Spanned<Expression>>,
+        captures: std::collections::HashMap<Identifier, Primary>,
+    },
+    /// A builtin function together with the arguments it has been applied to so far.
+    /// Only ever produced by the interpreter.
+    Builtin {
+        builtin: Builtin,
+        args: Vec<Spanned<Primary>>,
+    },
+    Array(Vec<Primary>),
+    Tuple(Vec<Primary>),
+    /// A value of a `record` type, with its fields in declaration order.
+    Record {
+        name: Identifier,
+        fields: Vec<(Identifier, Primary)>,
+    },
+    /// A constructor of the `data` type `data` together with the arguments it has been applied
+    /// to so far. It's a function until it has `arity` of them.
+    Constructor {
+        data: Identifier,
+        constructor: Identifier,
+        arity: usize,
+        args: Vec<Primary>,
     },
     String(String),
-    Int(f64),
+    Int(i64),
+    Float(f64),
     Bool(bool),
     Color(palette::Xyza<palette::white_point::D65, f64>),
     Unit,
@@ -147,21 +644,270 @@ impl PartialEq for Primary {
             (Primary::Bool(l), Primary::Bool(r)) => {
                 l == r
             },
+            // Colors are converted to XYZ, so the ones that only differ by rounding errors
+            // are the same.
             (Primary::Color(l), Primary::Color(r)) => {
-                l == r
+                let close = |l: f64, r: f64| (l - r).abs() < 1e-9;
+                close(l.x, r.x) && close(l.y, r.y) && close(l.z, r.z) && close(l.alpha, r.alpha)
             },
             (Primary::Int(l), Primary::Int(r)) => {
                 l == r
             },
+            (Primary::Float(l), Primary::Float(r)) => l == r,
+            // Number literals are `Int`s until they're used as a `Float`.
+            (Primary::Int(l), Primary::Float(r)) | (Primary::Float(r), Primary::Int(l)) => {
+                *l as f64 == *r
+            }
             (Primary::String(l), Primary::String(r)) => {
                 l == r 
             },
+            (Primary::Array(l), Primary::Array(r)) => l == r,
+            (Primary::Tuple(l), Primary::Tuple(r)) => l == r,
+            (
+                Primary::Record { name: l_name, fields: l_fields },
+                Primary::Record { name: r_name, fields: r_fields },
+            ) => l_name == r_name && l_fields == r_fields,
+            (
+                Primary::Constructor { constructor: l_constructor, args: l_args, .. },
+                Primary::Constructor { constructor: r_constructor, args: r_args, .. },
+            ) => l_constructor == r_constructor && l_args == r_args,
             (Primary::Unit, Primary::Unit) => true,
             _ => false
         }
     }
 }
 
+impl Primary {
+    /// Displays the value the way it would be written in easl, looking up record
+    /// and field names in `ident_map`.
+    pub fn display<'a>(&'a self, ident_map: &'a IdentifierMap) -> PrimaryDisplay<'a> {
+        PrimaryDisplay {
+            primary: self,
+            ident_map,
+        }
+    }
+}
+
+pub struct PrimaryDisplay<'a> {
+    primary: &'a Primary,
+    ident_map: &'a IdentifierMap,
+}
+
+impl std::fmt::Display for PrimaryDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |ident: &Identifier| self.ident_map.get(ident).cloned().unwrap_or_default();
+        let list = |f: &mut std::fmt::Formatter<'_>, elements: &[Primary]| {
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", element.display(self.ident_map))?;
+            }
+            Ok(())
+        };
+        match self.primary {
+            Primary::Lambda { .. } | Primary::Closure { .. } => write!(f, "<lambda>"),
+            Primary::Builtin { builtin, .. } => write!(f, "<builtin {}>", builtin.name()),
+            Primary::Array(elements) => {
+                write!(f, "[")?;
+                list(f, elements)?;
+                write!(f, "]")
+            }
+            Primary::Tuple(elements) => {
+                write!(f, "(")?;
+                list(f, elements)?;
+                write!(f, ")")
+            }
+            Primary::Record { name: record, fields } => {
+                write!(f, "{} {{ ", name(record))?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} = {}", name(field), value.display(self.ident_map))?;
+                }
+                write!(f, " }}")
+            }
+            Primary::Constructor { constructor, args, .. } => {
+                write!(f, "{}", name(constructor))?;
+                for arg in args {
+                    match arg {
+                        Primary::Constructor { args, .. } if !args.is_empty() => {
+                            write!(f, " ({})", arg.display(self.ident_map))?
+                        }
+                        _ => write!(f, " {}", arg.display(self.ident_map))?,
+                    }
+                }
+                Ok(())
+            }
+            Primary::String(string) => write!(f, "{string:?}"),
+            Primary::Int(int) => write!(f, "{int}"),
+            Primary::Float(float) => write!(f, "{float:?}"),
+            Primary::Bool(bool) => write!(f, "{}", if *bool { "True" } else { "False" }),
+            Primary::Color(color) => {
+                let srgba: palette::Srgba<u8> = palette::Srgba::from_color(*color).into_format();
+                write!(f, "#{:02X}{:02X}{:02X}", srgba.red, srgba.green, srgba.blue)?;
+                if srgba.alpha != u8::MAX {
+                    write!(f, "{:02X}", srgba.alpha)?;
+                }
+                Ok(())
+            }
+            Primary::Unit => write!(f, "()"),
+        }
+    }
+}
+
+/// Functions implemented by the interpreter instead of in easl itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    /// `length :: [a] -> Int`
+    Length,
+    /// `map :: (a -> b) -> [a] -> [b]`
+    Map,
+    /// `fold :: (b -> a -> b) -> b -> [a] -> b`, folding from the left.
+    Fold,
+    /// `rgb :: Float -> Float -> Float -> Color`, channels in `0..1` sRGB.
+    Rgb,
+    /// `hsv :: Float -> Float -> Float -> Color`, hue in degrees.
+    Hsv,
+    /// `cmy :: Float -> Float -> Float -> Color`, channels in `0..1`.
+    Cmy,
+    /// `xyz :: Float -> Float -> Float -> Color`, CIE XYZ with a D65 white point.
+    Xyz,
+    /// `alpha :: Float -> Color -> Color`, replaces the alpha of a color.
+    Alpha,
+    /// `mix :: Mix a => Float -> a -> a -> a`, interpolates linearly from the first value at 0
+    /// to the second at 1. `Int`s are rounded to the nearest one.
+    Mix,
+    /// `negate :: Num a => a -> a`, what unary `-` stands for.
+    Negate,
+    /// `toFloat :: Int -> Float`
+    ToFloat,
+    /// `floor :: Float -> Int`, the largest `Int` that isn't larger.
+    Floor,
+    /// `round :: Float -> Int`, the nearest `Int`, rounding halves away from zero.
+    Round,
+    /// `otherwise :: Bool`, `True`, for the last guard of a function.
+    Otherwise,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Length,
+        Builtin::Map,
+        Builtin::Fold,
+        Builtin::Rgb,
+        Builtin::Hsv,
+        Builtin::Cmy,
+        Builtin::Xyz,
+        Builtin::Alpha,
+        Builtin::Mix,
+        Builtin::Negate,
+        Builtin::ToFloat,
+        Builtin::Floor,
+        Builtin::Round,
+        Builtin::Otherwise,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Length => "length",
+            Builtin::Map => "map",
+            Builtin::Fold => "fold",
+            Builtin::Rgb => "rgb",
+            Builtin::Hsv => "hsv",
+            Builtin::Cmy => "cmy",
+            Builtin::Xyz => "xyz",
+            Builtin::Alpha => "alpha",
+            Builtin::Mix => "mix",
+            Builtin::Negate => "negate",
+            Builtin::ToFloat => "toFloat",
+            Builtin::Floor => "floor",
+            Builtin::Round => "round",
+            Builtin::Otherwise => "otherwise",
+        }
+    }
+
+    /// How many arguments the builtin takes before it is evaluated.
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Length => 1,
+            Builtin::Map => 2,
+            Builtin::Fold => 3,
+            Builtin::Rgb | Builtin::Hsv | Builtin::Cmy | Builtin::Xyz => 3,
+            Builtin::Alpha => 2,
+            Builtin::Mix => 3,
+            Builtin::Negate | Builtin::ToFloat | Builtin::Floor | Builtin::Round => 1,
+            Builtin::Otherwise => 0,
+        }
+    }
+}
+
+/// A class of types that share some operations, its methods. Every class is built in, but
+/// user declared types can be made instances of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Class {
+    /// `==` and `!=`. Every type except functions is an instance, comparing values
+    /// structurally unless they have an instance of their own.
+    Eq,
+    /// `<`, `>`, `<=` and `>=`, all defined by `<`.
+    Ord,
+    /// `+`, which `Int`, `String` and `Color` have.
+    Add,
+    /// `-`, `*`, `/`, `negate` and `fromInt`, which `Int`, `Float` and `Color` have. Number
+    /// literals can have any `Num` type and are turned into it with `fromInt`. `Int`s are
+    /// divided rounding towards zero.
+    Num,
+    /// `fromFloat`, which `Float` and `Color` have. Number literals with a decimal point can
+    /// have any `Fractional` type, so `0.5 * color` scales every component of a color but its
+    /// alpha.
+    Fractional,
+    /// `mix`, which `Int`, `Float` and `Color` have.
+    Mix,
+}
+
+impl Class {
+    pub const ALL: &'static [Class] =
+        &[Class::Eq, Class::Ord, Class::Add, Class::Num, Class::Fractional, Class::Mix];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::Eq => "Eq",
+            Class::Ord => "Ord",
+            Class::Add => "Add",
+            Class::Num => "Num",
+            Class::Fractional => "Fractional",
+            Class::Mix => "Mix",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Class> {
+        Class::ALL.iter().find(|class| class.name() == name).copied()
+    }
+
+    /// The methods an instance has to define.
+    pub fn methods(&self) -> &'static [&'static str] {
+        match self {
+            Class::Eq => &["=="],
+            Class::Ord => &["<"],
+            Class::Add => &["+"],
+            Class::Num => &["-", "*", "/", "negate", "fromInt"],
+            Class::Fractional => &["fromFloat"],
+            Class::Mix => &["mix"],
+        }
+    }
+
+    /// The class every instance of this one has to be an instance of as well.
+    pub fn superclass(&self) -> Option<Class> {
+        match self {
+            Class::Ord => Some(Class::Eq),
+            Class::Num => Some(Class::Add),
+            Class::Fractional => Some(Class::Num),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct Identifier {
@@ -173,27 +919,36 @@ pub struct IdentifierMap {
 impl IdentifierMap {
     pub fn new() -> Self {
         Self {
-            map: vec![
-                String::from("hsv"),
-                String::from("rgb"),
-                String::from("cmy"),
-                String::from("xyz"),
-                String::from("alpha"),
-            ]
-            .into_iter()
-            .map(|name| {
-                let mut hasher = DefaultHasher::new();
-                name.hash(&mut hasher);
-                (hasher.finish(), name)
-            })
-            .collect(),
+            map: Builtin::ALL
+                .iter()
+                .map(|builtin| builtin.name().to_string())
+                .map(|name| {
+                    let mut hasher = DefaultHasher::new();
+                    name.hash(&mut hasher);
+                    (hasher.finish(), name)
+                })
+                .collect(),
         }
     }
     pub fn create_identifier(&mut self, name: String) -> Result<Identifier, Identifier> {
         let mut hasher = DefaultHasher::new();
         name.hash(&mut hasher);
-        let handle = hasher.finish();
-        if self.map.get(&handle).is_some() {
+        self.insert(hasher.finish(), name)
+    }
+    /// Like `create_identifier`, for a top level definition of the module in `file`. Modules in
+    /// different directories can have the same name, so their definitions are told apart by
+    /// the file they're in.
+    pub fn create_module_identifier(
+        &mut self,
+        file: FileId,
+        name: String,
+    ) -> Result<Identifier, Identifier> {
+        let mut hasher = DefaultHasher::new();
+        (file, &name).hash(&mut hasher);
+        self.insert(hasher.finish(), name)
+    }
+    fn insert(&mut self, handle: u64, name: String) -> Result<Identifier, Identifier> {
+        if self.map.contains_key(&handle) {
             return Err(Identifier { handle });
         }
         self.map.insert(handle, name);
@@ -209,15 +964,154 @@ impl IdentifierMap {
             .map(|(key, _)| Identifier { handle: *key })
     }
 }
+impl Default for IdentifierMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
     Infer,
     String,
     Int,
+    Float,
     Color,
     Bool,
     Unit,
     Array(Box<Type>),
+    Tuple(Vec<Type>),
+    /// A user declared type, such as a `record`.
+    Named(Identifier),
+    /// A `type` alias. It is the same type as `target` but keeps its name around so that it
+    /// can be shown in diagnostics.
+    Alias { ident: Identifier, target: Box<Type> },
     Fun { input: Box<Type>, output: Box<Type> },
+    /// A type variable, which stands for any type. Definitions whose type has variables can
+    /// be used with a different type for them at every use.
+    Var(Identifier),
+    /// `Num a => Type`, a type whose variables can only stand for instances of some classes.
+    Constrained {
+        constraints: Vec<(Class, Identifier)>,
+        type_: Box<Type>,
+    },
+}
+
+impl Type {
+    /// The number of arguments a value of this type can be applied to.
+    pub fn arity(&self) -> usize {
+        match self {
+            Type::Fun { output, .. } => 1 + output.arity(),
+            Type::Alias { target, .. } => target.arity(),
+            Type::Constrained { type_, .. } => type_.arity(),
+            _ => 0,
+        }
+    }
+
+    /// The type with every alias in it replaced by what it stands for. Two types are the
+    /// same if their expansions are equal.
+    pub fn expand_aliases(&self) -> Type {
+        match self {
+            Type::Alias { target, .. } => target.expand_aliases(),
+            Type::Array(element) => Type::Array(Box::new(element.expand_aliases())),
+            Type::Tuple(elements) => {
+                Type::Tuple(elements.iter().map(Type::expand_aliases).collect())
+            }
+            Type::Fun { input, output } => Type::Fun {
+                input: Box::new(input.expand_aliases()),
+                output: Box::new(output.expand_aliases()),
+            },
+            Type::Constrained { constraints, type_ } => Type::Constrained {
+                constraints: constraints.clone(),
+                type_: Box::new(type_.expand_aliases()),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Displays the type the way it would be written in easl. Aliases are shown by name
+    /// unless `expand_aliases` is set.
+    pub fn display<'a>(
+        &'a self,
+        ident_map: &'a IdentifierMap,
+        expand_aliases: bool,
+    ) -> TypeDisplay<'a> {
+        TypeDisplay {
+            type_: self,
+            ident_map,
+            expand_aliases,
+        }
+    }
+}
+
+pub struct TypeDisplay<'a> {
+    type_: &'a Type,
+    ident_map: &'a IdentifierMap,
+    expand_aliases: bool,
+}
+
+impl TypeDisplay<'_> {
+    /// Displays a type nested in this one the same way.
+    fn nested<'a>(&'a self, type_: &'a Type) -> TypeDisplay<'a> {
+        type_.display(self.ident_map, self.expand_aliases)
+    }
+}
+
+impl std::fmt::Display for TypeDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.type_ {
+            Type::Infer => write!(f, "_"),
+            Type::String => write!(f, "String"),
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Color => write!(f, "Color"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Unit => write!(f, "()"),
+            Type::Array(element) => write!(f, "[{}]", self.nested(element)),
+            Type::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", self.nested(element))?;
+                }
+                write!(f, ")")
+            }
+            Type::Alias { target, .. } if self.expand_aliases => {
+                write!(f, "{}", self.nested(target))
+            }
+            Type::Named(ident) | Type::Alias { ident, .. } | Type::Var(ident) => {
+                write!(f, "{}", self.ident_map.get(ident).cloned().unwrap_or_default())
+            }
+            Type::Fun { input, output } => {
+                let parenthesize = match input.as_ref() {
+                    Type::Fun { .. } => true,
+                    Type::Alias { .. } if self.expand_aliases => {
+                        matches!(input.expand_aliases(), Type::Fun { .. })
+                    }
+                    _ => false,
+                };
+                match parenthesize {
+                    true => write!(f, "({}) -> {}", self.nested(input), self.nested(output)),
+                    false => write!(f, "{} -> {}", self.nested(input), self.nested(output)),
+                }
+            }
+            Type::Constrained { constraints, type_ } => {
+                let name = |ident| self.ident_map.get(ident).cloned().unwrap_or_default();
+                if let [(class, variable)] = constraints.as_slice() {
+                    let type_ = self.nested(type_);
+                    return write!(f, "{} {} => {}", class.name(), name(variable), type_);
+                }
+                write!(f, "(")?;
+                for (i, (class, variable)) in constraints.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} {}", class.name(), name(variable))?;
+                }
+                write!(f, ") => {}", self.nested(type_))
+            }
+        }
+    }
 }
@@ -0,0 +1,155 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike the AST this keeps every character of the source around, including whitespace,
+//! comments and punctuation, so that concatenating the text of its tokens gives back the
+//! source it was built from. It's used by tooling like the formatter.
+
+use std::ops::Range;
+
+use pest::{iterators::Pair, Parser};
+
+use super::{layout::Layout, EaslParser, ParserError, ParserErrors, Rule};
+use crate::{source::Source, utils::pest_span_to_range};
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub rule: Rule,
+    pub span: Range<usize>,
+    pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Newline,
+    /// `-- ...`, without the newline ending it.
+    Comment,
+    /// Keywords like `let` or `where`.
+    Keyword,
+    /// Punctuation like `=`, `::` or `(`.
+    Symbol,
+    /// Text matched by a grammar rule that isn't broken up any further,
+    /// like identifiers, operators and literals.
+    Leaf(Rule),
+}
+
+impl TokenKind {
+    /// Whether the token can be dropped without changing the meaning of the code.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::Newline | TokenKind::Comment)
+    }
+}
+
+/// Parses `source` into a concrete syntax tree rooted at a `file` node.
+///
+/// The `;`s the layout inserts between the items of blocks aren't in the source, so the tokens
+/// are taken from it rather than from the text that was parsed. There they are whitespace.
+pub fn parse(source: Source<'_>) -> Result<SyntaxNode, ParserErrors> {
+    let layout = Layout::new(source.text);
+    let mut file = EaslParser::parse(Rule::file, &layout.text).map_err(|error| ParserErrors {
+        errors: vec![ParserError::syntax_error(error, source, 0)],
+    })?;
+    Ok(build_node(file.next().unwrap(), source.text))
+}
+
+fn build_node(pair: Pair<'_, Rule>, source: &str) -> SyntaxNode {
+    let span = pest_span_to_range(pair.as_span());
+    let mut children = Vec::new();
+    let mut position = span.start;
+    for child in pair.clone().into_inner() {
+        let child_span = pest_span_to_range(child.as_span());
+        tokenize_gap(source, position..child_span.start, &mut children);
+        position = child_span.end;
+        children.push(match is_leaf(&child) {
+            true => SyntaxElement::Token(SyntaxToken {
+                kind: TokenKind::Leaf(child.as_rule()),
+                text: source[child_span.clone()].to_string(),
+                span: child_span,
+            }),
+            false => SyntaxElement::Node(build_node(child, source)),
+        });
+    }
+    tokenize_gap(source, position..span.end, &mut children);
+    SyntaxNode { rule: pair.as_rule(), span, children }
+}
+
+/// Literals are kept whole even though the grammar splits them up, so that they are
+/// never reformatted.
+fn is_leaf(pair: &Pair<'_, Rule>) -> bool {
+    let literals = [Rule::string_l, Rule::int_l, Rule::float_l, Rule::negative_literal];
+    literals.contains(&pair.as_rule())
+        || pair.clone().into_inner().next().is_none()
+}
+
+/// Splits the text between two pairs, which the grammar doesn't produce pairs for,
+/// into tokens.
+fn tokenize_gap(source: &str, gap: Range<usize>, tokens: &mut Vec<SyntaxElement>) {
+    let mut position = gap.start;
+    while position < gap.end {
+        let rest = &source[position..gap.end];
+        let char_len = |predicate: fn(char) -> bool| {
+            rest.find(|c: char| !predicate(c)).unwrap_or(rest.len())
+        };
+        let (kind, len) = if rest.starts_with("--") {
+            (TokenKind::Comment, rest.find(['\r', '\n']).unwrap_or(rest.len()))
+        } else if rest.starts_with("\r\n") {
+            (TokenKind::Newline, 2)
+        } else if rest.starts_with('\n') {
+            (TokenKind::Newline, 1)
+        } else if rest.starts_with([' ', '\t']) {
+            (TokenKind::Whitespace, char_len(|c| c == ' ' || c == '\t'))
+        } else if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            (TokenKind::Keyword, char_len(|c| c.is_alphanumeric() || c == '_'))
+        } else if ["::", "->", "=>", ".."].iter().any(|symbol| rest.starts_with(symbol)) {
+            (TokenKind::Symbol, 2)
+        } else {
+            (TokenKind::Symbol, rest.chars().next().unwrap().len_utf8())
+        };
+        tokens.push(SyntaxElement::Token(SyntaxToken {
+            kind,
+            span: position..position + len,
+            text: rest[..len].to_string(),
+        }));
+        position += len;
+    }
+}
+
+impl SyntaxNode {
+    /// The tokens of this node and its descendants in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// The exact source text this node was built from.
+    pub fn text(&self) -> String {
+        self.tokens().into_iter().map(|token| token.text.as_str()).collect()
+    }
+
+    /// The first direct child node matching `rule`.
+    pub fn child(&self, rule: Rule) -> Option<&SyntaxNode> {
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(node) if node.rule == rule => Some(node),
+            _ => None,
+        })
+    }
+}
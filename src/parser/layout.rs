@@ -0,0 +1,248 @@
+//! Indentation-sensitive layout.
+//!
+//! A line that is indented continues the statement above it, which the grammar handles on its
+//! own by treating a line break followed by an indented line as whitespace. The items of
+//! `let`, `where` and `case ... of` blocks are separated by `;` though, and those can be left
+//! out by starting every item on its own line, at the same column as the first one:
+//!
+//! ```text
+//! area shape = case shape of
+//!     Circle r -> pi * r * r
+//!     Rect w h -> w * h
+//!   where
+//!     pi = 3.14159
+//! ```
+//!
+//! `Layout` inserts the `;`s that were left out before the text is parsed. Each one replaces
+//! the last character of the indentation in front of it, so that the text keeps its length and
+//! every span into it is also a span into the source.
+
+use std::ops::Range;
+
+use pest::Parser;
+
+use super::{EaslParser, ParserError, Rule};
+use crate::source::Source;
+
+/// Tabs indent to the next multiple of this many columns.
+const TAB_WIDTH: usize = 8;
+
+/// Keywords that start a block at the token following them.
+const BLOCK_KEYWORDS: [&str; 3] = ["let", "where", "of"];
+
+const OPERATOR_CHARS: &str = "!$%&*+./<=>?@^|-~:";
+
+pub struct Layout {
+    /// The source with the `;`s between the items of blocks.
+    pub text: String,
+    /// Where `;`s were inserted.
+    pub separators: Vec<usize>,
+    /// The indented lines that don't start an item, used to explain syntax errors on them.
+    continuations: Vec<Continuation>,
+}
+
+/// An indented line that continues the one above it.
+struct Continuation {
+    /// The first token of the line.
+    first: Range<usize>,
+    /// The end of the line, without its line break.
+    end: usize,
+    column: usize,
+    /// The block the line is closest to being aligned with, if it's in or leaves one.
+    block: Option<Block>,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    keyword: &'static str,
+    column: usize,
+    /// The first token of the first item.
+    first: Range<usize>,
+}
+
+struct Token<'a> {
+    /// The offset into the line.
+    start: usize,
+    column: usize,
+    text: &'a str,
+}
+
+impl Layout {
+    pub fn new(source: &str) -> Self {
+        let mut text = source.to_string();
+        let mut separators = Vec::new();
+        let mut continuations = Vec::new();
+
+        // The blocks the current line is in, innermost last.
+        let mut blocks: Vec<Block> = Vec::new();
+        // A keyword whose block starts at the next token.
+        let mut opening: Option<&'static str> = None;
+        // `let` blocks that were closed by a line indented less than them, whose `in` hasn't
+        // been seen yet.
+        let mut unfinished_lets = 0;
+
+        let mut offset = 0;
+        for line in source.split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+            let tokens = tokens(line);
+            let Some(first) = tokens.first() else {
+                // Blank lines and comments don't matter.
+                continue;
+            };
+            let column = first.column;
+            let first_span = line_start + first.start..line_start + first.start + first.text.len();
+
+            if column == 0 {
+                // A new statement.
+                blocks.clear();
+                opening = None;
+                unfinished_lets = 0;
+            } else {
+                let closest = blocks
+                    .iter()
+                    .rev()
+                    .min_by_key(|block| block.column.abs_diff(column))
+                    .cloned();
+                while let Some(block) = blocks.last().filter(|block| column < block.column) {
+                    if block.keyword == "let" {
+                        unfinished_lets += 1;
+                    }
+                    blocks.pop();
+                }
+
+                let deeper = blocks.last().is_none_or(|block| column > block.column);
+                let starts_item = match opening.take() {
+                    Some(keyword) if deeper => {
+                        blocks.push(Block { keyword, column, first: first_span.clone() });
+                        true
+                    }
+                    _ if blocks.last().is_some_and(|block| block.column == column) => {
+                        let separator = first_span.start - 1;
+                        text.replace_range(separator..separator + 1, ";");
+                        separators.push(separator);
+                        true
+                    }
+                    _ => false,
+                };
+                if !starts_item {
+                    continuations.push(Continuation {
+                        first: first_span,
+                        end: line_start + line.trim_end().len(),
+                        column,
+                        block: closest,
+                    });
+                }
+            }
+
+            for (index, token) in tokens.iter().enumerate() {
+                let start = line_start + token.start;
+                if index > 0 {
+                    if let Some(keyword) = opening.take() {
+                        let first = start..start + token.text.len();
+                        blocks.push(Block { keyword, column: token.column, first });
+                    }
+                }
+                let keyword = BLOCK_KEYWORDS.iter().find(|keyword| **keyword == token.text);
+                if let Some(keyword) = keyword {
+                    opening = Some(keyword);
+                } else if token.text == "in" {
+                    let innermost_let = blocks.iter().rposition(|block| block.keyword == "let");
+                    if unfinished_lets > 0 {
+                        unfinished_lets -= 1;
+                    } else if let Some(innermost_let) = innermost_let {
+                        blocks.truncate(innermost_let);
+                    }
+                }
+            }
+        }
+
+        Self { text, separators, continuations }
+    }
+
+    /// Explains a syntax error at `position` with the indentation of its line, if the line
+    /// looks like it was meant to start a statement or an item of a block but is indented as
+    /// if it continued the line above it.
+    pub fn indentation_error(&self, position: usize, source: Source<'_>) -> Option<ParserError> {
+        let line = self
+            .continuations
+            .iter()
+            .find(|line| (line.first.start..=line.end).contains(&position))?;
+        let text = &self.text[line.first.start..line.end];
+        let starts_item = [Rule::statement, Rule::binding, Rule::case_arm]
+            .into_iter()
+            .any(|rule| EaslParser::parse(rule, text).is_ok());
+        if !starts_item {
+            return None;
+        }
+        Some(match &line.block {
+            Some(block) => ParserError::MisalignedLine {
+                source_code: source.named(),
+                keyword: block.keyword,
+                found: line.column + 1,
+                expected: block.column + 1,
+                line: line.first.clone().into(),
+                block: block.first.clone().into(),
+            },
+            None => ParserError::UnexpectedIndentation {
+                source_code: source.named(),
+                line: line.first.clone().into(),
+            },
+        })
+    }
+}
+
+/// Splits a line into tokens, roughly like the grammar does. Only keywords and columns matter
+/// here, so it's enough to skip strings and comments and to keep identifiers and operators
+/// together.
+fn tokens(line: &str) -> Vec<Token<'_>> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut column = 0;
+    let mut index = 0;
+    while let Some(&(start, char)) = chars.get(index) {
+        let length_while = |predicate: fn(char) -> bool| {
+            chars[index..].iter().take_while(|(_, char)| predicate(*char)).count()
+        };
+        let length = match char {
+            ' ' | '\r' | '\n' => {
+                column += 1;
+                index += 1;
+                continue;
+            }
+            '\t' => {
+                column = (column / TAB_WIDTH + 1) * TAB_WIDTH;
+                index += 1;
+                continue;
+            }
+            '-' if line[start..].starts_with("--") => break,
+            '"' => {
+                let mut end = index + 1;
+                while let Some(&(_, char)) = chars.get(end) {
+                    end += match char {
+                        '\\' => 2,
+                        '"' => {
+                            end += 1;
+                            break;
+                        }
+                        '\r' | '\n' => break,
+                        _ => 1,
+                    };
+                }
+                end.min(chars.len()) - index
+            }
+            char if char.is_ascii_alphanumeric() || char == '_' => {
+                length_while(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_'))
+            }
+            char if OPERATOR_CHARS.contains(char) => {
+                length_while(|char| OPERATOR_CHARS.contains(char))
+            }
+            _ => 1,
+        };
+        let end = chars.get(index + length).map_or(line.len(), |(end, _)| *end);
+        tokens.push(Token { start, column, text: &line[start..end] });
+        column += length;
+        index += length;
+    }
+    tokens
+}
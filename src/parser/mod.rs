@@ -1,8 +1,16 @@
 pub mod ast;
+pub mod cst;
+pub mod layout;
 
-use miette::{Diagnostic, SourceSpan};
-// use palette::{FromColor, Xyza};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use palette::{FromColor, Srgba, Xyza};
 use pest::{
+    error::InputLocation,
     iterators::{Pair, Pairs},
     Parser,
 };
@@ -11,97 +19,1165 @@ use pest_derive::Parser;
 use thiserror::Error;
 
 use crate::{
-    parser::ast::{BinaryOperator, Expression, Primary, UnaryOperator},
+    parser::ast::{
+        Alternative, Associativity, BinaryOperator, CaseArm, Class, Expression, Fixity, Pattern,
+        Primary, UnaryOperator,
+    },
+    source::{FileId, Source},
     utils::pest_span_to_range,
 };
 
-use self::ast::{IdentifierMap, Statement, Type, Spanned};
+use self::ast::{Identifier, IdentifierMap, Statement, Type, Spanned};
+use self::layout::Layout;
 
 #[derive(Parser)]
 #[grammar = "parser/easl.pest"]
 pub struct EaslParser;
 
-pub fn parse(source: &str) -> Result<(Vec<Statement>, IdentifierMap), ParserError> {
-    let mut ident_map = IdentifierMap::new();
-    Ok((
-        build_ast(
-            EaslParser::parse(Rule::file, source)?,
-            source,
-            &mut ident_map,
-        )?,
-        ident_map,
-    ))
+pub fn parse(source: &str) -> Result<(Vec<Statement>, IdentifierMap), ParserErrors> {
+    let (statements, ident_map, errors) = parse_recovering(source);
+    if !errors.is_empty() {
+        return Err(ParserErrors { errors });
+    }
+    Ok((statements, ident_map))
+}
+
+/// Parses as much of `source` as possible. Statements and expressions that couldn't be
+/// parsed are replaced by `Statement::Error` and `Expression::Error` and every error
+/// encountered along the way is returned.
+pub fn parse_recovering(source: &str) -> (Vec<Statement>, IdentifierMap, Vec<ParserError>) {
+    let source = Source { file: FileId::default(), name: "<input>", text: source };
+    let (module, errors) = parse_module(source, None, &HashMap::new());
+    (module.statements, module.ident_map, errors)
+}
+
+/// A parsed file.
+pub struct Module {
+    pub statements: Vec<Statement>,
+    pub ident_map: IdentifierMap,
+    pub exports: Exports,
+    pub warnings: Vec<ParserWarning>,
+}
+
+/// What a file makes visible to the files that include or import it. Only what the file
+/// declares itself, what it includes or imports has to be included or imported again.
+#[derive(Debug, Clone, Default)]
+pub struct Exports {
+    /// Top level definitions by their unqualified name.
+    pub values: HashMap<String, Identifier>,
+    pub records: HashMap<Identifier, Vec<Identifier>>,
+    pub data: HashMap<Identifier, Vec<Identifier>>,
+    pub constructors: HashMap<Identifier, usize>,
+    pub aliases: HashMap<Identifier, Type>,
+    pub fixities: HashMap<String, Fixity>,
+    pub instances: HashSet<(Class, Identifier)>,
+}
+
+/// A file that another file depends on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dependency {
+    /// `include "path"`
+    Include(String),
+    /// `import Module`, with or without a list of names.
+    Import(String),
+}
+
+/// Parses a file that is part of a larger program, like `parse_recovering`.
+///
+/// The top level definitions of modules with a `name` are prefixed with it, so that
+/// `sunset` in `Palettes` becomes `Palettes.sunset`, and told apart by the file of `source`, so
+/// that they can't clash with definitions of other modules, even ones with the same name.
+/// `dependencies` holds the exports of every file `source` includes or imports.
+pub fn parse_module(
+    source: Source<'_>,
+    name: Option<&str>,
+    dependencies: &HashMap<Dependency, Exports>,
+) -> (Module, Vec<ParserError>) {
+    let mut state = ParserState::new();
+    state.module = name.map(|name| (name.to_string(), source.file));
+    let layout = Layout::new(source.text);
+    let statements = match EaslParser::parse(Rule::file, &layout.text) {
+        Ok(pairs) => build_ast(pairs, source, &layout, dependencies, &mut state),
+        Err(error) => {
+            state.errors.push(ParserError::syntax_error(error, source, 0));
+            Vec::new()
+        }
+    };
+    let module = Module {
+        statements,
+        ident_map: state.ident_map,
+        exports: state.exports,
+        warnings: state.warnings,
+    };
+    (module, state.errors)
+}
+
+/// The `include`s and `import`s of `source`, without parsing the rest of it.
+pub fn dependencies(source: Source<'_>) -> Vec<Spanned<Dependency>> {
+    let layout = Layout::new(source.text);
+    let Ok(mut pairs) = EaslParser::parse(Rule::file, &layout.text) else {
+        return Vec::new();
+    };
+    pairs
+        .next()
+        .into_iter()
+        .flat_map(|file| file.into_inner())
+        .filter_map(|statement| {
+            let statement = statement.into_inner().next()?;
+            let span = pest_span_to_range(statement.as_span());
+            let target = statement.clone().into_inner().next()?;
+            let dependency = match statement.as_rule() {
+                Rule::include => Dependency::Include(build_string(target, source).ok()?),
+                Rule::import => Dependency::Import(target.as_str().to_string()),
+                _ => return None,
+            };
+            Some(Spanned::new(source.file, span, dependency))
+        })
+        .collect()
+}
+
+/// Bookkeeping threaded through the AST builders.
+pub struct ParserState {
+    pub ident_map: IdentifierMap,
+
+    /// Builtins, imports and the top level definitions seen so far, by the name they are
+    /// referred to with.
+    globals: HashMap<String, Identifier>,
+    /// Names bound by enclosing lambdas, `let`s and `where`s, innermost last.
+    scopes: Vec<HashMap<String, Identifier>>,
+    /// The definitions of enclosing `let`s and `where`s that aren't in scope yet, because they
+    /// are being built or come later, with the span of their name.
+    unbound_locals: Vec<(String, Range<usize>)>,
+    /// Declared `record`s and their fields in declaration order.
+    records: HashMap<Identifier, Vec<Identifier>>,
+    /// Declared `data` types and their constructors.
+    data: HashMap<Identifier, Vec<Identifier>>,
+    /// The constructors of all `data` types and how many arguments they take.
+    constructors: HashMap<Identifier, usize>,
+    /// Declared `type` aliases and the types they stand for.
+    aliases: HashMap<Identifier, Type>,
+    /// Fixities declared for user defined operators.
+    fixities: HashMap<String, Fixity>,
+    /// The classes user declared types have been made instances of.
+    instances: HashSet<(Class, Identifier)>,
+    /// Errors that have been recovered from so far.
+    errors: Vec<ParserError>,
+    warnings: Vec<ParserWarning>,
+    /// The name top level definitions are prefixed with and the file of the module, see
+    /// `parse_module`.
+    module: Option<(String, FileId)>,
+    exports: Exports,
+}
+
+impl ParserState {
+    pub fn new() -> Self {
+        let ident_map = IdentifierMap::new();
+        let globals = ident_map
+            .map
+            .iter()
+            .map(|(handle, name)| (name.clone(), Identifier { handle: *handle }))
+            .collect();
+        Self {
+            ident_map,
+            globals,
+            scopes: Vec::new(),
+            unbound_locals: Vec::new(),
+            records: HashMap::new(),
+            data: HashMap::new(),
+            constructors: HashMap::new(),
+            aliases: HashMap::new(),
+            fixities: HashMap::new(),
+            instances: HashSet::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            module: None,
+            exports: Exports::default(),
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> Identifier {
+        match self.ident_map.create_identifier(name.to_string()) {
+            Ok(ident) | Err(ident) => ident,
+        }
+    }
+
+    /// Makes `ident` visible until the innermost scope is popped.
+    fn bind(&mut self, ident: Identifier) {
+        let name = self.ident_map.get(&ident).cloned().unwrap_or_default();
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name, ident),
+            None => self.globals.insert(name, ident),
+        };
+    }
+
+    /// The identifier of the top level definition `name` in this module.
+    fn global(&mut self, name: &str) -> Identifier {
+        match &self.module {
+            Some((module, file)) => {
+                let name = format!("{module}.{name}");
+                match self.ident_map.create_module_identifier(*file, name) {
+                    Ok(ident) | Err(ident) => ident,
+                }
+            }
+            None => self.intern(name),
+        }
+    }
+
+    /// Makes the top level definition `name` visible, here and to other modules.
+    fn define(&mut self, name: &str) -> Identifier {
+        let ident = self.global(name);
+        self.globals.insert(name.to_string(), ident);
+        self.exports.values.insert(name.to_string(), ident);
+        ident
+    }
+
+    /// Makes the definitions of an included or imported file visible. All of them can be
+    /// referred to as `module.name` and the ones in `unqualified` as just `name`.
+    fn import(&mut self, exports: &Exports, module: Option<&str>, unqualified: &[&str]) {
+        for (name, ident) in &exports.values {
+            if let Some(module) = module {
+                self.globals.insert(format!("{module}.{name}"), *ident);
+            }
+            if unqualified.contains(&name.as_str()) {
+                self.globals.insert(name.clone(), *ident);
+            }
+        }
+        self.records.extend(exports.records.clone());
+        self.data.extend(exports.data.clone());
+        self.constructors.extend(exports.constructors.clone());
+        self.aliases.extend(exports.aliases.clone());
+        self.fixities.extend(exports.fixities.clone());
+        self.instances.extend(exports.instances.iter().copied());
+    }
+
+    fn fixity(&self, operator: &str) -> Fixity {
+        self.fixities
+            .get(operator)
+            .copied()
+            .or_else(|| BinaryOperator::from_symbol(operator).map(|operator| operator.fixity()))
+            .unwrap_or_default()
+    }
+
+    /// Looks up `name` in the enclosing scopes, falling back to the globals.
+    fn resolve(&self, name: &str) -> Option<Identifier> {
+        let local = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+        local.or_else(|| self.globals.get(name)).copied()
+    }
+
+    /// Resolves a variable, reporting it and returning an error node if it isn't in scope.
+    fn variable(&mut self, name: &str, span: Range<usize>, source: Source<'_>) -> Expression {
+        match self.resolve(name) {
+            Some(ident) => Expression::Variable(ident),
+            None => {
+                let local = self.unbound_locals.iter().rev().find(|(local, _)| local == name);
+                let error = match local {
+                    Some((_, definition)) => ParserError::LocalNotYetDefined {
+                        source_code: source.named(),
+                        ident: name.to_string(),
+                        this_use: span.into(),
+                        definition: definition.clone().into(),
+                    },
+                    None => ParserError::UnknownIdentifier {
+                    source_code: source.named(),
+                        ident: name.to_string(),
+                        unknown_identifier: span.into(),
+                    },
+                };
+                self.errors.push(error);
+                Expression::Error
+            }
+        }
+    }
+}
+
+impl Default for ParserState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 fn build_ast(
     mut pairs: Pairs<'_, Rule>,
-    source: &str,
-    ident_map: &mut IdentifierMap,
-) -> Result<Vec<Statement>, ParserError> {
+    source: Source<'_>,
+    layout: &Layout,
+    dependencies: &HashMap<Dependency, Exports>,
+    state: &mut ParserState,
+) -> Vec<Statement> {
     let mut ast = Vec::new();
 
     let Some(file) = pairs.next() else {
-        return Ok(ast);
+        return ast;
     };
     let statements = file.into_inner();
 
-    for statement in statements {
-        ast.push(build_statement(statement, source, ident_map)?);
+    // Like fixities, includes and imports apply to the whole file.
+    for statement in statements.clone() {
+        let Some(statement) = statement.into_inner().next() else {
+            continue;
+        };
+        let mut inner = statement.clone().into_inner();
+        match statement.as_rule() {
+            Rule::include => {
+                let Ok(path) = build_string(inner.next().unwrap(), source) else {
+                    continue;
+                };
+                if let Some(exports) = dependencies.get(&Dependency::Include(path)) {
+                    let names: Vec<_> = exports.values.keys().map(String::as_str).collect();
+                    state.import(exports, None, &names);
+                }
+            }
+            Rule::import => {
+                let module = inner.next().unwrap().as_str();
+                let Some(exports) = dependencies.get(&Dependency::Import(module.to_string()))
+                else {
+                    continue;
+                };
+                let names: Vec<_> = match inner.next() {
+                    Some(list) => list
+                        .into_inner()
+                        .filter(|name| {
+                            let exported = exports.values.contains_key(name.as_str());
+                            if !exported {
+                                state.errors.push(ParserError::UnknownImport {
+                                    source_code: source.named(),
+                                    module: module.to_string(),
+                                    ident: name.as_str().to_string(),
+                                    unknown_import: pest_span_to_range(name.as_span()).into(),
+                                });
+                            }
+                            exported
+                        })
+                        .map(|name| name.as_str())
+                        .collect(),
+                    None => exports.values.keys().map(String::as_str).collect(),
+                };
+                state.import(exports, Some(module), &names);
+            }
+            _ => {}
+        }
     }
 
-    Ok(ast)
+    // Fixity declarations apply to the whole file, not just the lines after them. So do
+    // definitions, which can refer to each other in any order.
+    for statement in statements.clone() {
+        let Some(statement) = statement.into_inner().next() else {
+            continue;
+        };
+        match statement.as_rule() {
+            Rule::fixity => {
+                let (fixity, operators) = build_fixity(statement);
+                for operator in operators {
+                    state.fixities.insert(operator.as_str().to_string(), fixity);
+                    state.exports.fixities.insert(operator.as_str().to_string(), fixity);
+                }
+            }
+            Rule::assignment => {
+                state.define(name_str(&assignment_name(&statement)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut assignments = HashMap::new();
+    let mut ascriptions = HashMap::new();
+    for clauses in group_clauses(statements) {
+        let last = &clauses[clauses.len() - 1];
+        let span = statement_span(&clauses[0]).start..statement_span(last).end;
+        let statement = clauses[0].clone();
+        if statement.as_rule() == Rule::invalid_statement {
+            // Keep the name defined so that its uses aren't reported as well.
+            if let Some(name) = statement.clone().into_inner().next() {
+                state.define(name_str(&name));
+            }
+            state.errors.push(invalid_statement_error(statement, source, layout));
+            ast.push(Statement::Error);
+            continue;
+        }
+        let statement = match clauses.len() {
+            1 => build_statement(statement, source, state),
+            _ => build_definition(&clauses, source, state),
+        };
+        let statement = match statement {
+            Ok(statement) => statement,
+            Err(error) => {
+                state.errors.push(error);
+                // The error might have been raised before the scopes of the statement were popped.
+                state.scopes.clear();
+                state.unbound_locals.clear();
+                Statement::Error
+            }
+        };
+        match &statement {
+            Statement::Assignment { ident, params, .. } => {
+                let previous = assignments.insert(*ident, (span.clone(), params.clone()));
+                if let Some((first_assignment, _)) = previous {
+                    state.errors.push(ParserError::OverridenIdentifier {
+                        source_code: source.named(),
+                        first_assignment: first_assignment.into(),
+                        second_assignment: span.into(),
+                    });
+                }
+            }
+            Statement::TypeAscription { ident, type_ } => {
+                ascriptions.insert(*ident, type_.clone());
+            }
+            _ => {}
+        }
+        ast.push(statement);
+    }
+
+    for (ident, (_, params)) in assignments {
+        let (Some(ascription), Some(first), Some(last)) =
+            (ascriptions.get(&ident), params.first(), params.last())
+        else {
+            continue;
+        };
+        if params.len() > ascription.inner.arity() {
+            state.errors.push(ParserError::ArityMismatch {
+                source_code: source.named(),
+                ident: state.ident_map.get(&ident).cloned().unwrap_or_default(),
+                expected: ascription.inner.arity(),
+                found: params.len(),
+                ascription: ascription.span.clone().into(),
+                params: (first.span.start..last.span.end).into(),
+            });
+        }
+    }
+
+    ast
+}
+
+/// Reparses a line that was skipped as an `invalid_statement` on its own to find out why it
+/// isn't a valid statement.
+fn invalid_statement_error(
+    statement: Pair<'_, Rule>,
+    source: Source<'_>,
+    layout: &Layout,
+) -> ParserError {
+    let Err(error) = EaslParser::parse(Rule::statement, statement.as_str()) else {
+        return ParserError::internal_grammar_error(source, statement.as_span());
+    };
+    // The error is relative to the line, move it back to where the line is in the file.
+    let start = statement.as_span().start();
+    let (InputLocation::Pos(position) | InputLocation::Span((position, _))) = error.location;
+    layout
+        .indentation_error(start + position, source)
+        .unwrap_or_else(|| ParserError::syntax_error(error, source, start))
+}
+
+/// The span of a statement without its trailing newline.
+fn statement_span(statement: &Pair<'_, Rule>) -> Range<usize> {
+    match statement.as_rule() {
+        Rule::statement => statement
+            .clone()
+            .into_inner()
+            .next()
+            .map(|inner| statement_span(&inner))
+            .unwrap_or_else(|| pest_span_to_range(statement.as_span())),
+        _ => pest_span_to_range(statement.as_span()),
+    }
+}
+
+/// Builds the clauses of a top level definition, see `build_binding`.
+fn build_definition(
+    clauses: &[Pair<'_, Rule>],
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Statement, ParserError> {
+    let clauses: Vec<_> = clauses.iter().filter_map(as_assignment).collect();
+    let ident = state.define(name_str(&assignment_name(&clauses[0])));
+    let (_, params, expr) = build_binding(clauses, source, state)?;
+    Ok(Statement::Assignment { ident, params, expr })
 }
 
 fn build_statement(
     statement: Pair<'_, Rule>,
-    source: &str,
-    ident_map: &mut IdentifierMap,
+    source: Source<'_>,
+    state: &mut ParserState,
 ) -> Result<Statement, ParserError> {
     let mut inner = statement.clone().into_inner();
     match statement.as_rule() {
-        Rule::statement => build_statement(inner.next().unwrap(), source, ident_map),
-        Rule::assignment => {
-            let ident = ident_map
-                .create_identifier(inner.next().unwrap().to_string())
-                .or(Err(ParserError::OverridenIdentifier {
-                    source_code: source.to_string(),
-                    second_assignment: pest_span_to_range(statement.as_span()).into(),
-                }))?;
-            let expr = build_expression(inner.next().unwrap(), source, ident_map)?;
-
-            Ok(Statement::Assignment { ident, expr })
-        }
+        Rule::statement => build_statement(inner.next().unwrap(), source, state),
+        Rule::assignment => build_definition(&[statement], source, state),
         Rule::type_ascription => {
             let ident = inner.next().unwrap();
-            let ident = match ident_map.create_identifier(ident.as_str().to_string()) {
-                Ok(ident) | Err(ident) => ident
-            };
+            let ident = state.global(name_str(&ident));
+
+            let annotation = inner.next().unwrap();
+            let span = pest_span_to_range(annotation.as_span());
+            let type_ = build_type(annotation, source, state, true)?;
+            Ok(Statement::TypeAscription { ident, type_: Spanned::new(source.file, span, type_) })
+        }
+        Rule::record => {
+            let ident = state.intern(inner.next().unwrap().as_str());
+            // Registered up front so that records can contain themselves, e.g. in arrays.
+            state.records.insert(ident, Vec::new());
+
+            let mut fields: Vec<(Spanned<Identifier>, Spanned<Type>)> = Vec::new();
+            for field in inner {
+                let mut field = field.into_inner();
+                let name = field.next().unwrap();
+                let span = pest_span_to_range(name.as_span());
+                let name = Spanned::new(source.file, span, state.intern(name.as_str()));
+                let first = fields.iter().find(|(first, _)| first.inner == name.inner);
+                if let Some((first, _)) = first {
+                    return Err(ParserError::DuplicateField {
+                        source_code: source.named(),
+                        first_field: first.span.clone().into(),
+                        second_field: name.span.into(),
+                    });
+                }
+                let annotation = field.next().unwrap();
+                let span = pest_span_to_range(annotation.as_span());
+                let type_ = build_type(annotation, source, state, false)?;
+                fields.push((name, Spanned::new(source.file, span, type_)));
+            }
+
+            let names: Vec<_> = fields.iter().map(|(name, _)| name.inner).collect();
+            state.records.insert(ident, names.clone());
+            state.exports.records.insert(ident, names);
+            Ok(Statement::Record { ident, fields })
+        }
+        Rule::data => {
+            let ident = state.intern(inner.next().unwrap().as_str());
+            // Registered up front so that data types can be recursive.
+            state.data.insert(ident, Vec::new());
+
+            let mut constructors = Vec::new();
+            for constructor in inner {
+                let mut constructor = constructor.into_inner();
+                let name = constructor.next().unwrap();
+                let span = pest_span_to_range(name.as_span());
+                let types = constructor
+                    .map(|type_| {
+                        let span = pest_span_to_range(type_.as_span());
+                        let type_ = build_type(type_, source, state, false)?;
+                        Ok(Spanned::new(source.file, span, type_))
+                    })
+                    .collect::<Result<Vec<_>, ParserError>>()?;
+                let name = state.define(name.as_str());
+                state.constructors.insert(name, types.len());
+                state.exports.constructors.insert(name, types.len());
+                constructors.push((Spanned::new(source.file, span, name), types));
+            }
+
+            let names: Vec<_> = constructors.iter().map(|(name, _)| name.inner).collect();
+            state.data.insert(ident, names.clone());
+            state.exports.data.insert(ident, names);
+            Ok(Statement::Data { ident, constructors })
+        }
+        Rule::type_alias => {
+            let ident = state.intern(inner.next().unwrap().as_str());
 
-            let type_ = build_type(inner.next().unwrap(), source)?;
-            Ok(Statement::TypeAscription { ident, type_ })
+            let target = inner.next().unwrap();
+            let span = pest_span_to_range(target.as_span());
+            // Built before the alias is registered, so aliases can't refer to themselves.
+            let type_ = build_type(target, source, state, false)?;
+            state.aliases.insert(ident, type_.clone());
+            state.exports.aliases.insert(ident, type_.clone());
+            Ok(Statement::TypeAlias { ident, type_: Spanned::new(source.file, span, type_) })
+        }
+        Rule::newtype => {
+            let ident = state.intern(inner.next().unwrap().as_str());
+            state.data.insert(ident, Vec::new());
+
+            let name = inner.next().unwrap();
+            let span = pest_span_to_range(name.as_span());
+            let wrapped = inner.next().unwrap();
+            let wrapped_span = pest_span_to_range(wrapped.as_span());
+            let type_ = build_type(wrapped, source, state, false)?;
+            let constructor = state.define(name.as_str());
+            state.constructors.insert(constructor, 1);
+            state.data.insert(ident, vec![constructor]);
+            state.exports.constructors.insert(constructor, 1);
+            state.exports.data.insert(ident, vec![constructor]);
+            Ok(Statement::Data {
+                ident,
+                constructors: vec![(
+                    Spanned::new(source.file, span, constructor),
+                    vec![Spanned::new(source.file, wrapped_span, type_)],
+                )],
+            })
+        }
+        Rule::instance => {
+            let name = inner.next().unwrap();
+            let class_span = pest_span_to_range(name.as_span());
+            let class = Class::from_name(name.as_str()).ok_or_else(|| ParserError::UnknownClass {
+                source_code: source.named(),
+                ident: name.as_str().to_string(),
+                unknown_class: class_span.clone().into(),
+            })?;
+
+            let name = inner.next().unwrap();
+            let type_span = pest_span_to_range(name.as_span());
+            let ident = state.intern(name.as_str());
+            if !state.records.contains_key(&ident) && !state.data.contains_key(&ident) {
+                return Err(ParserError::UnknownType {
+                    source_code: source.named(),
+                    ident: name.as_str().to_string(),
+                    unknown_type: type_span.into(),
+                });
+            }
+            // Every type but functions is an instance of `Eq` already, comparing structurally.
+            if let Some(superclass) = class.superclass().filter(|class| *class != Class::Eq) {
+                if !state.instances.contains(&(superclass, ident)) {
+                    return Err(ParserError::MissingSuperclassInstance {
+                        source_code: source.named(),
+                        class: class.name(),
+                        superclass: superclass.name(),
+                        ident: name.as_str().to_string(),
+                        instance: (class_span.start..type_span.end).into(),
+                    });
+                }
+            }
+
+            let mut methods: Vec<(Spanned<Identifier>, Spanned<Expression>)> = Vec::new();
+            for clauses in group_clauses(inner) {
+                let (method, _, expr) = build_binding(clauses, source, state)?;
+                let name = state.ident_map.get(&method.inner).cloned().unwrap_or_default();
+                let repeated = methods.iter().any(|(first, _)| first.inner == method.inner);
+                if !class.methods().contains(&name.as_str()) || repeated {
+                    return Err(ParserError::UnknownMethod {
+                        source_code: source.named(),
+                        class: class.name(),
+                        ident: name,
+                        unknown_method: method.span.into(),
+                    });
+                }
+                methods.push((method, expr));
+            }
+            let missing: Vec<_> = class
+                .methods()
+                .iter()
+                .filter(|method| {
+                    !methods.iter().any(|(defined, _)| {
+                        state.ident_map.get(&defined.inner).map(String::as_str) == Some(**method)
+                    })
+                })
+                .map(|method| format!("'{method}'"))
+                .collect();
+            if !missing.is_empty() {
+                return Err(ParserError::MissingMethods {
+                    source_code: source.named(),
+                    methods: missing.join(", "),
+                    this_instance: pest_span_to_range(statement.as_span()).into(),
+                });
+            }
+
+            if !state.instances.insert((class, ident)) {
+                return Err(ParserError::DuplicateInstance {
+                    source_code: source.named(),
+                    class: class.name(),
+                    ident: name.as_str().to_string(),
+                    instance: (class_span.start..type_span.end).into(),
+                });
+            }
+            state.exports.instances.insert((class, ident));
+            Ok(Statement::Instance {
+                class: Spanned::new(source.file, class_span, class),
+                type_: Spanned::new(source.file, type_span, ident),
+                methods,
+            })
+        }
+        Rule::fixity => {
+            let (fixity, operators) = build_fixity(statement);
+            let operators = operators
+                .into_iter()
+                .map(|operator| {
+                    let span = pest_span_to_range(operator.as_span());
+                    Spanned::new(source.file, span, state.intern(operator.as_str()))
+                })
+                .collect();
+            Ok(Statement::Fixity { fixity, operators })
         }
         Rule::include => Ok(Statement::Include {
-            source: inner.next().unwrap().to_string(),
+            source: build_string(inner.next().unwrap(), source)?,
         }),
+        Rule::import => {
+            let module = inner.next().unwrap().as_str().to_string();
+            let names = inner.next().map(|list| {
+                list.into_inner()
+                    .map(|name| {
+                        let qualified = format!("{module}.{}", name.as_str());
+                        let ident = match state.globals.get(&qualified) {
+                            Some(ident) => *ident,
+                            None => state.intern(name.as_str()),
+                        };
+                        Spanned::new(source.file, pest_span_to_range(name.as_span()), ident)
+                    })
+                    .collect()
+            });
+            Ok(Statement::Import { module, names })
+        }
         Rule::EOI => Ok(Statement::EOI),
-        _ => {
-            return Err(ParserError::internal_grammar_error(
-                source,
-                statement.as_span(),
-            ))
+        _ => Err(ParserError::internal_grammar_error(source, statement.as_span())),
+    }
+}
+
+/// One equation of a function, `name params | guard = body | ... where bindings`.
+struct Clause {
+    ident: Spanned<Identifier>,
+    params: Vec<Spanned<Pattern>>,
+    /// The name and the parameters.
+    head: Range<usize>,
+    /// The `where` clause, in scope for the guards and bodies.
+    bindings: Bindings,
+    /// Tried in order.
+    bodies: Vec<GuardedBody>,
+}
+
+struct GuardedBody {
+    /// `| guard = body`, or just the body if there's no guard.
+    span: Range<usize>,
+    guard: Option<Spanned<Expression>>,
+    body: Spanned<Expression>,
+    /// Whether the guard always holds, because there is none or it's `otherwise` or `True`.
+    unconditional: bool,
+}
+
+/// A name bound by `build_binding`, with its parameters and the value built from them.
+type Binding = (Spanned<Identifier>, Vec<Spanned<Pattern>>, Spanned<Expression>);
+
+/// Builds the `assignment` pairs defining a name, desugaring their parameters into lambdas
+/// and their `where` clauses into `let`s. The bound name itself is *not* brought into scope.
+///
+/// A function with guards or several clauses becomes a lambda matching its arguments against
+/// each clause in turn, so `f 0 = a; f n | n < 0 = b | otherwise = c` is
+/// `\x -> case x of 0 -> a; n | n < 0 -> b; n | otherwise -> c`.
+fn build_binding(
+    clauses: Vec<Pair<'_, Rule>>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Binding, ParserError> {
+    let mut clauses = clauses
+        .into_iter()
+        .map(|clause| build_clause(clause, source, state))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let [Clause { bodies, .. }] = clauses.as_slice() {
+        if let [GuardedBody { guard: None, .. }] = bodies.as_slice() {
+            let Clause { ident, params, bindings, mut bodies, .. } = clauses.pop().unwrap();
+            // `f a b = body where bindings` is sugar for `f = \a -> \b -> let bindings in body`
+            let body = bodies.pop().unwrap().body;
+            let body = match bindings.last() {
+                Some((_, last)) => Spanned::new(
+                    source.file,
+                    body.span.start..last.span.end,
+                    Expression::Let { bindings, body: Box::new(body) },
+                ),
+                None => body,
+            };
+            return Ok((ident, params.clone(), build_lambda(params, body)));
+        }
+    }
+
+    let first = &clauses[0];
+    if let Some(clause) = clauses.iter().find(|clause| clause.params.len() != first.params.len()) {
+        return Err(ParserError::ClauseArityMismatch {
+            source_code: source.named(),
+            ident: state.ident_map.get(&first.ident.inner).cloned().unwrap_or_default(),
+            expected: first.params.len(),
+            found: clause.params.len(),
+            first: first.head.clone().into(),
+            clause: clause.head.clone().into(),
+        });
+    }
+    state.warnings.extend(unreachable_clauses(&clauses, source));
+
+    let (ident, params) = (first.ident.clone(), first.params.clone());
+    let file = source.file;
+    let span = first.head.start..clauses.last().unwrap().bodies.last().unwrap().body.span.end;
+    let arguments: Vec<_> = params
+        .iter()
+        .enumerate()
+        .map(|(index, param)| {
+            // Not valid identifiers, so they can't shadow anything.
+            let ident = state.intern(&format!("(argument {index})"));
+            (ident, param.span.clone())
+        })
+        .collect();
+    let variable = |(ident, span): &(Identifier, Range<usize>)| {
+        Spanned::new(file, span.clone(), Expression::Variable(*ident))
+    };
+    // Several arguments are matched at once as a tuple.
+    let scrutinee = match arguments.as_slice() {
+        [] => Expression::Primary(Spanned::new(file, first.head.clone(), Primary::Unit)),
+        [argument] => variable(argument).inner,
+        arguments => Expression::Tuple(arguments.iter().map(variable).collect()),
+    };
+    let scrutinee = Box::new(Spanned::new(file, first.head.clone(), scrutinee));
+    let mut arms = Vec::new();
+    for Clause { params, head, bindings, bodies, .. } in clauses {
+        let pattern = match params.len() {
+            0 => Spanned::new(file, head, Pattern::Wildcard),
+            1 => params.into_iter().next().unwrap(),
+            _ => {
+                let span = params[0].span.start..params[params.len() - 1].span.end;
+                Spanned::new(file, span, Pattern::Tuple(params))
+            }
+        };
+        let alternatives = bodies
+            .into_iter()
+            .map(|GuardedBody { guard, body, .. }| Alternative { guard, body })
+            .collect();
+        arms.push(CaseArm { pattern, bindings, alternatives });
+    }
+    let case = Spanned::new(file, span, Expression::Case { scrutinee, arms });
+    let arguments = arguments
+        .into_iter()
+        .map(|(ident, span)| Spanned::new(file, span, Pattern::Variable(ident)))
+        .collect();
+    Ok((ident, params, build_lambda(arguments, case)))
+}
+
+fn build_clause(
+    assignment: Pair<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Clause, ParserError> {
+    let mut inner = assignment.into_inner().peekable();
+
+    // The name comes first, or between the parameters for `a <+> b = ...`.
+    let mut ident = None;
+    state.scopes.push(HashMap::new());
+    let mut params = Vec::new();
+    let mut head: Option<Range<usize>> = None;
+    let is_lhs = |pair: &Pair<'_, Rule>| {
+        matches!(pair.as_rule(), Rule::name | Rule::operator | Rule::param)
+    };
+    while let Some(pair) = inner.next_if(is_lhs) {
+        let span = pest_span_to_range(pair.as_span());
+        head = Some(head.map_or(span.start, |head| head.start)..span.end);
+        if pair.as_rule() == Rule::param {
+            params.push(build_pattern(pair, source, state)?);
+        } else {
+            ident = Some(Spanned::new(source.file, span, state.intern(name_str(&pair))));
+        }
+    }
+    let (ident, head) = (ident.unwrap(), head.unwrap());
+
+    let (bodies, where_clause): (Vec<_>, Vec<_>) =
+        inner.partition(|pair| pair.as_rule() != Rule::where_clause);
+    // The `where` clause comes after the bodies in the source but has to be in scope for them.
+    state.scopes.push(HashMap::new());
+    let bindings = match where_clause.into_iter().next() {
+        Some(where_clause) => build_bindings(where_clause.into_inner(), source, state)?,
+        None => Vec::new(),
+    };
+
+    let bodies = bodies
+        .into_iter()
+        .map(|body| {
+            // Without the whitespace before the next guard.
+            let start = body.as_span().start();
+            let span = start..start + body.as_str().trim_end().len();
+            let (guard, body) = match body.as_rule() {
+                Rule::guarded_rhs => {
+                    let mut inner = body.into_inner();
+                    let guard = build_expression(inner.next().unwrap(), source, state)?;
+                    (Some(guard), build_expression(inner.next().unwrap(), source, state)?)
+                }
+                _ => (None, build_expression(body, source, state)?),
+            };
+            let unconditional = match guard.as_ref().map(|guard| &guard.inner) {
+                None | Some(Expression::Primary(Spanned { inner: Primary::Bool(true), .. })) => {
+                    true
+                }
+                Some(Expression::Variable(ident)) => {
+                    state.ident_map.get(ident).is_some_and(|name| name == "otherwise")
+                }
+                Some(_) => false,
+            };
+            Ok(GuardedBody { span, guard, body, unconditional })
+        })
+        .collect::<Result<_, ParserError>>()?;
+    state.scopes.pop();
+    state.scopes.pop();
+
+    Ok(Clause { ident, params, head, bindings, bodies })
+}
+
+/// Warns about clauses and guards of a function that are never used, because an earlier
+/// one without a guard matches everything they match.
+fn unreachable_clauses(clauses: &[Clause], source: Source<'_>) -> Vec<ParserWarning> {
+    let mut warnings = Vec::new();
+    // The patterns of the clauses that always apply once they match, with the span to point
+    // out for them.
+    let mut catch_alls: Vec<(&[Spanned<Pattern>], Range<usize>)> = Vec::new();
+    let covering = |catch_alls: &[(&[Spanned<Pattern>], Range<usize>)], clause: &Clause| {
+        catch_alls.iter().find_map(|(params, span)| {
+            let mut params = params.iter().zip(&clause.params);
+            let subsumes = params.all(|(param, other)| param.inner.subsumes(&other.inner));
+            subsumes.then(|| span.clone())
+        })
+    };
+    for clause in clauses {
+        if let Some(covering) = covering(&catch_alls, clause) {
+            warnings.push(ParserWarning::UnreachableClause {
+                source_code: source.named(),
+                unreachable: clause.head.clone().into(),
+                covering: covering.into(),
+            });
+            continue;
+        }
+        for body in &clause.bodies {
+            if let Some(covering) = covering(&catch_alls, clause) {
+                warnings.push(ParserWarning::UnreachableClause {
+                    source_code: source.named(),
+                    unreachable: body.span.clone().into(),
+                    covering: covering.into(),
+                });
+            } else if body.unconditional {
+                let span = match body.guard {
+                    Some(_) => body.span.clone(),
+                    None => clause.head.clone(),
+                };
+                catch_alls.push((&clause.params, span));
+            }
+        }
+    }
+    warnings
+}
+
+/// Splits `pairs` into the definitions they make. A function can be defined by several
+/// clauses, `assignment`s with parameters of the same name right after each other, which end
+/// up together. Every other pair is on its own.
+fn group_clauses<'a>(pairs: impl IntoIterator<Item = Pair<'a, Rule>>) -> Vec<Vec<Pair<'a, Rule>>> {
+    let mut groups: Vec<Vec<Pair<'a, Rule>>> = Vec::new();
+    for pair in pairs {
+        let continues = groups.last().is_some_and(|group| {
+            let (Some(first), Some(next)) = (as_assignment(&group[0]), as_assignment(&pair)) else {
+                return false;
+            };
+            let has_params = first.clone().into_inner().any(|pair| pair.as_rule() == Rule::param);
+            has_params && name_str(&assignment_name(&first)) == name_str(&assignment_name(&next))
+        });
+        match groups.last_mut() {
+            Some(group) if continues => group.push(pair),
+            _ => groups.push(vec![pair]),
+        }
+    }
+    groups
+}
+
+/// The `assignment` a `statement` or `binding` pair is made of, if it is one.
+fn as_assignment<'a>(pair: &Pair<'a, Rule>) -> Option<Pair<'a, Rule>> {
+    match pair.as_rule() {
+        Rule::assignment => Some(pair.clone()),
+        Rule::statement | Rule::binding => as_assignment(&pair.clone().into_inner().next()?),
+        _ => None,
+    }
+}
+
+/// Desugars the parameters of a function or lambda into nested lambdas with one parameter
+/// each, `\a b -> body` into `\a -> \b -> body`.
+fn build_lambda(params: Vec<Spanned<Pattern>>, body: Spanned<Expression>) -> Spanned<Expression> {
+    params.into_iter().rev().fold(body, |body, param| {
+        let (file, span) = (body.file, param.span.start..body.span.end);
+        let lambda = Primary::Lambda {
+            param: Box::new(param),
+            body: Box::new(body),
+        };
+        Spanned::new(
+            file,
+            span.clone(),
+            Expression::Primary(Spanned::new(file, span, lambda)),
+        )
+    })
+}
+
+/// The `name` or `operator` pair of an `assignment`.
+fn assignment_name<'a>(assignment: &Pair<'a, Rule>) -> Pair<'a, Rule> {
+    let mut inner = assignment.clone().into_inner();
+    inner.find(|pair| matches!(pair.as_rule(), Rule::name | Rule::operator)).unwrap()
+}
+
+/// The name a `name`, `operator` or `ident` pair binds, without the parentheses
+/// around operators.
+fn name_str<'a>(pair: &Pair<'a, Rule>) -> &'a str {
+    match pair.as_rule() {
+        Rule::name => pair.clone().into_inner().next().unwrap().as_str(),
+        _ => pair.as_str(),
+    }
+}
+
+fn build_fixity(fixity: Pair<'_, Rule>) -> (Fixity, Vec<Pair<'_, Rule>>) {
+    let mut inner = fixity.into_inner();
+    let associativity = match inner.next().unwrap().as_rule() {
+        Rule::infixl => Associativity::Left,
+        Rule::infixr => Associativity::Right,
+        _ => Associativity::None,
+    };
+    // The grammar only allows a single digit.
+    let precedence = inner.next().unwrap().as_str().parse().unwrap();
+    (
+        Fixity {
+            associativity,
+            precedence,
+        },
+        inner.collect(),
+    )
+}
+
+/// Groups `operands` and the `operators` between them by precedence and associativity.
+fn build_operation(
+    operands: Vec<Spanned<Expression>>,
+    operators: Vec<Pair<'_, Rule>>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Spanned<Expression>, ParserError> {
+    let mut operands = operands.into_iter();
+    let mut output = vec![operands.next().unwrap()];
+    let mut pending: Vec<(Pair<'_, Rule>, Fixity)> = Vec::new();
+
+    let reduce = |output: &mut Vec<Spanned<Expression>>,
+                  operator: Pair<'_, Rule>,
+                  state: &mut ParserState| {
+        let rhs = output.pop().unwrap();
+        let lhs = output.pop().unwrap();
+        let span = lhs.span.start..rhs.span.end;
+        let expression = match BinaryOperator::from_symbol(operator.as_str()) {
+            Some(operator) => Expression::Binary {
+                operator,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            },
+            // `a <+> b` is sugar for `(<+>) a b`
+            None => {
+                let span = pest_span_to_range(operator.as_span());
+                let function = state.variable(operator.as_str(), span.clone(), source);
+                let function = Spanned::new(source.file, span, function);
+                let partial = Spanned::new(
+                    source.file,
+                    lhs.span.start..function.span.end,
+                    Expression::FunctionApplication {
+                        function: Box::new(function),
+                        argument: Box::new(lhs),
+                    },
+                );
+                Expression::FunctionApplication {
+                    function: Box::new(partial),
+                    argument: Box::new(rhs),
+                }
+            }
+        };
+        output.push(Spanned::new(source.file, span, expression));
+    };
+
+    for (operator, rhs) in operators.into_iter().zip(operands) {
+        let fixity = state.fixity(operator.as_str());
+        while let Some((previous, previous_fixity)) = pending.last() {
+            if previous_fixity.precedence == fixity.precedence
+                && (previous_fixity.associativity != fixity.associativity
+                    || fixity.associativity == Associativity::None)
+            {
+                return Err(ParserError::AmbiguousOperators {
+                    source_code: source.named(),
+                    first: pest_span_to_range(previous.as_span()).into(),
+                    second: pest_span_to_range(operator.as_span()).into(),
+                });
+            }
+            let binds_previous_first = previous_fixity.precedence > fixity.precedence
+                || (previous_fixity.precedence == fixity.precedence
+                    && fixity.associativity == Associativity::Left);
+            if !binds_previous_first {
+                break;
+            }
+            let (previous, _) = pending.pop().unwrap();
+            reduce(&mut output, previous, state);
+        }
+        pending.push((operator, fixity));
+        output.push(rhs);
+    }
+    while let Some((operator, _)) = pending.pop() {
+        reduce(&mut output, operator, state);
+    }
+
+    Ok(output.pop().unwrap())
+}
+
+/// Builds the `assignment`s in `bindings` one after another, each in scope for the next
+/// and for `body`.
+fn build_let(
+    span: Range<usize>,
+    bindings: Pairs<'_, Rule>,
+    body: Pair<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Spanned<Expression>, ParserError> {
+    state.scopes.push(HashMap::new());
+    let bindings = build_bindings(bindings, source, state)?;
+    let body = Box::new(build_expression(body, source, state)?);
+    state.scopes.pop();
+
+    Ok(Spanned::new(
+        source.file,
+        span,
+        Expression::Let {
+            bindings,
+            body,
+        },
+    ))
+}
+
+/// The bindings of a `let`, see `Expression::Let`.
+type Bindings = Vec<(Spanned<Pattern>, Spanned<Expression>)>;
+
+/// Builds the `binding`s of a `let` or `where` into the innermost scope, each one in scope
+/// for the ones after it.
+fn build_bindings(
+    bindings: Pairs<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Bindings, ParserError> {
+    let groups = group_clauses(bindings);
+    let enclosing = state.unbound_locals.len();
+    for clauses in groups.iter().rev() {
+        if let Some(assignment) = as_assignment(&clauses[0]) {
+            let name = assignment_name(&assignment);
+            let span = pest_span_to_range(name.as_span());
+            state.unbound_locals.push((name_str(&name).to_string(), span));
+        }
+    }
+    let mut built = Vec::new();
+    for clauses in groups {
+        let binding = clauses[0].clone().into_inner().next().unwrap();
+        match binding.as_rule() {
+            Rule::assignment => {
+                let clauses = clauses.iter().filter_map(as_assignment).collect();
+                let (ident, _, expr) = build_binding(clauses, source, state)?;
+                state.unbound_locals.pop();
+                state.bind(ident.inner);
+                let pattern = Spanned::new(source.file, ident.span, Pattern::Variable(ident.inner));
+                built.push((pattern, expr));
+            }
+            Rule::pattern_binding => {
+                let mut inner = binding.into_inner();
+                let pattern = inner.next().unwrap();
+                let expr = build_expression(inner.next().unwrap(), source, state)?;
+                built.push((build_pattern(pattern, source, state)?, expr));
+            }
+            _ => return Err(ParserError::internal_grammar_error(source, binding.as_span())),
         }
     }
+    state.unbound_locals.truncate(enclosing);
+    Ok(built)
 }
 
 fn build_expression(
-    expression: Pair<'_, Rule>,
-    source: &str,
-    ident_map: &mut IdentifierMap,
+    mut expression: Pair<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
 ) -> Result<Spanned<Expression>, ParserError> {
+    // Every precedence level has its own rule, so even small expressions are nested deeply, and
+    // this function's frames are large. The levels that are skipped aren't recursed into.
+    while only_wraps(&expression) {
+        expression = expression.into_inner().next().unwrap();
+    }
     let mut inner = expression.clone().into_inner();
     macro_rules! build_next {
         () => {
-            build_expression(inner.next().unwrap(), source, ident_map)?
+            build_expression(inner.next().unwrap(), source, state)?
         };
     }
     macro_rules! unless_1_inner {
@@ -113,6 +1189,7 @@ fn build_expression(
     }
 
     Ok(Spanned::new(
+        source.file,
         pest_span_to_range(expression.as_span()),
         match expression.as_rule() {
         Rule::expression => return Ok(build_next!()),
@@ -122,57 +1199,19 @@ fn build_expression(
             let else_ = Box::new(build_next!());
             Expression::If { cond, then, else_ }
         }),
-        Rule::comparison => unless_1_inner!({
-            let lhs = Box::new(build_next!());
-            let operator = match inner.next().unwrap().as_rule() {
-                Rule::equivalent => BinaryOperator::Equivalent,
-                Rule::not_equivalent => BinaryOperator::NotEquivalent,
-                Rule::less_than => BinaryOperator::LessThan,
-                Rule::less_than_or_eq => BinaryOperator::LessThanOrEqual,
-                Rule::greater_than => BinaryOperator::GreaterThan,
-                Rule::greater_than_or_eq => BinaryOperator::GreaterThanOrEqual,
-                _ => {
-                    return Err(ParserError::internal_grammar_error(
-                        source,
-                        expression.as_span(),
-                    ))
+        Rule::operation => unless_1_inner!({
+            let mut operands = Vec::new();
+            let mut operators = Vec::new();
+            for pair in inner {
+                match pair.as_rule() {
+                    Rule::operator => operators.push(pair),
+                    _ => operands.push(build_expression(pair, source, state)?),
                 }
-            };
-            let rhs = Box::new(build_next!());
-            Expression::Binary { lhs, operator, rhs }
-        }),
-        Rule::term => unless_1_inner!({
-            let lhs = Box::new(build_next!());
-            let operator = match inner.next().unwrap().as_rule() {
-                Rule::add => BinaryOperator::Add,
-                Rule::sub => BinaryOperator::Sub,
-                _ => {
-                    return Err(ParserError::internal_grammar_error(
-                        source,
-                        expression.as_span(),
-                    ))
-                }
-            };
-            let rhs = Box::new(build_next!());
-            Expression::Binary { lhs, operator, rhs }
-        }),
-        Rule::factor => unless_1_inner!({
-            let lhs = Box::new(build_next!());
-            let operator = match inner.next().unwrap().as_rule() {
-                Rule::mul => BinaryOperator::Mul,
-                Rule::div => BinaryOperator::Div,
-                _ => {
-                    return Err(ParserError::internal_grammar_error(
-                        source,
-                        expression.as_span(),
-                    ))
-                }
-            };
-            let rhs = Box::new(build_next!());
-            Expression::Binary { lhs, operator, rhs }
+            }
+            return build_operation(operands, operators, source, state);
         }),
         Rule::unary => unless_1_inner!({
-            let operator = match inner.next().unwrap().as_rule() {
+            let operator = match inner.next().unwrap().into_inner().next().unwrap().as_rule() {
                 Rule::not => UnaryOperator::Not,
                 Rule::negative => UnaryOperator::Negative,
                 _ => {
@@ -185,66 +1224,194 @@ fn build_expression(
             let rhs = Box::new(build_next!());
             Expression::Unary { operator, rhs }
         }),
-        Rule::function_application => unless_1_inner!({
-            let function = Box::new(build_next!());
-            let argument = Box::new(build_next!());
-            Expression::FunctionApplication { function, argument }
-        }),
+        Rule::access => {
+            let variable = inner.next().unwrap();
+            let mut fields = inner.peekable();
+            // `Module.name` refers to a definition of an imported module, unless `Module` is
+            // a variable itself.
+            let qualified = fields.peek().and_then(|field| {
+                let module = variable.clone().into_inner().next()?;
+                if module.as_rule() != Rule::ident || state.resolve(module.as_str()).is_some() {
+                    return None;
+                }
+                let ident = state.globals.get(&format!("{}{}", module.as_str(), field.as_str()))?;
+                let span = variable.as_span().start()..field.as_span().end();
+                Some(Spanned::new(source.file, span, Expression::Variable(*ident)))
+            });
+            let record = match qualified {
+                Some(qualified) => {
+                    fields.next();
+                    qualified
+                }
+                None => build_expression(variable, source, state)?,
+            };
+            return Ok(fields.fold(record, |record, field| {
+                // Without the leading `.`
+                let name = state.intern(&field.as_str()[1..]);
+                let span = field.as_span().start() + 1..field.as_span().end();
+                let field = Spanned::new(source.file, span, name);
+                Spanned::new(
+                    source.file,
+                    record.span.start..field.span.end,
+                    Expression::FieldAccess {
+                        record: Box::new(record),
+                        field,
+                    },
+                )
+            }));
+        }
+        Rule::function_application => {
+            let function = build_next!();
+            let mut application = function;
+            for argument in inner {
+                // The argument's own span leaves out the parentheses around it, and the pair
+                // includes the whitespace after it.
+                let end = argument.as_span().start() + argument.as_str().trim_end().len();
+                let argument = build_expression(argument, source, state)?;
+                application = Spanned::new(
+                    source.file,
+                    application.span.start..end,
+                    Expression::FunctionApplication {
+                        function: Box::new(application),
+                        argument: Box::new(argument),
+                    },
+                );
+            }
+            return Ok(application);
+        }
         Rule::variable => {
-            println!("{:?}", expression.as_span());
             let next = inner.next().unwrap();
             match next.as_rule() {
                 Rule::ident => {
-                    Expression::Variable(ident_map.get_from_name(expression.as_str()).ok_or(
-                        ParserError::UnknownIdentifier {
-                            source_code: source.to_string(),
-                            ident: next.as_str().to_string(),
-                            unknown_identifier: pest_span_to_range(expression.as_span()).into(),
+                    let span = pest_span_to_range(expression.as_span());
+                    state.variable(next.as_str(), span, source)
+                }
+                Rule::wildcard => {
+                    state.errors.push(ParserError::WildcardExpression {
+                        source_code: source.named(),
+                        wildcard: pest_span_to_range(next.as_span()).into(),
+                    });
+                    Expression::Error
+                }
+                _ => return build_expression(next, source, state),
+            }
+        }
+        Rule::section => {
+            let operator = inner.next().unwrap();
+            let span = pest_span_to_range(expression.as_span());
+            match BinaryOperator::from_symbol(operator.as_str()) {
+                // `(+)` is sugar for `\lhs -> \rhs -> lhs + rhs`
+                Some(binary) => {
+                    // Not valid identifiers, so they can't shadow anything.
+                    let lhs = state.intern("(lhs)");
+                    let rhs = state.intern("(rhs)");
+                    let variable = |ident| {
+                        let variable = Expression::Variable(ident);
+                        Box::new(Spanned::new(source.file, span.clone(), variable))
+                    };
+                    let body = Spanned::new(
+                        source.file,
+                        span.clone(),
+                        Expression::Binary {
+                            operator: binary,
+                            lhs: variable(lhs),
+                            rhs: variable(rhs),
                         },
-                    )?)
+                    );
+                    let param = |ident| {
+                        Spanned::new(source.file, span.clone(), Pattern::Variable(ident))
+                    };
+                    return Ok(build_lambda(vec![param(lhs), param(rhs)], body));
+                }
+                None => {
+                    let span = pest_span_to_range(operator.as_span());
+                    state.variable(operator.as_str(), span, source)
                 }
-                _ => {
-                    return Ok(build_expression(next, source, ident_map)?)
-                },
             }
         }
         Rule::primary => return Ok(build_next!()),
         Rule::literal => return Ok(build_next!()),
+        Rule::let_in => {
+            let body = inner.next_back().unwrap();
+            return build_let(pest_span_to_range(expression.as_span()), inner, body, source, state);
+        }
+        Rule::case_of => {
+            let scrutinee = Box::new(build_next!());
+            let mut arms = Vec::new();
+            for arm in inner {
+                let mut arm = arm.into_inner();
+                state.scopes.push(HashMap::new());
+                let pattern = build_pattern(arm.next().unwrap(), source, state)?;
+                let mut alternatives = Vec::new();
+                if let Some(Rule::guarded_arm) = arm.peek().map(|pair| pair.as_rule()) {
+                    for guarded in arm {
+                        let mut guarded = guarded.into_inner();
+                        let guard = build_expression(guarded.next().unwrap(), source, state)?;
+                        let body = build_expression(guarded.next().unwrap(), source, state)?;
+                        alternatives.push(Alternative { guard: Some(guard), body });
+                    }
+                } else {
+                    let body = build_expression(arm.next().unwrap(), source, state)?;
+                    alternatives.push(Alternative { guard: None, body });
+                }
+                arms.push(CaseArm { pattern, bindings: Vec::new(), alternatives });
+                state.scopes.pop();
+            }
+            Expression::Case { scrutinee, arms }
+        }
         Rule::lambda => unless_1_inner!({
-            println!("{:?}", inner);
-            println!("{:?}", expression.as_span());
-            println!("{:?}", expression.as_rule());
-            let param = inner.next().unwrap().as_str().to_string();
-            let param = match ident_map.create_identifier(param) {
-                Ok(param) | Err(param) => param
-            };
-            let span = pest_span_to_range(expression.as_span()).into();
-            let body = Box::new(build_next!());
-            Expression::Primary(Spanned {
-                inner: Primary::Lambda { param, body },
-                span,
-            })
+            let body = inner.next_back().unwrap();
+            state.scopes.push(HashMap::new());
+            let params = inner
+                .map(|param| build_pattern(param, source, state))
+                .collect::<Result<_, _>>()?;
+            let body = build_expression(body, source, state)?;
+            state.scopes.pop();
+            build_lambda(params, body).inner
         }),
-        Rule::int_l => Expression::Primary(Spanned {
-            inner: Primary::Int(match inner.next().unwrap().as_rule() {
-                Rule::hex_int => i64::from_str_radix(expression.as_str(), 16).unwrap() as f64,
-                Rule::binary_int => i64::from_str_radix(expression.as_str(), 2).unwrap() as f64,
-                Rule::decimal_int => expression.as_str().parse::<f64>().unwrap(),
+        Rule::int_l => {
+            let digits = inner.next().unwrap();
+            let text = digits.as_str().replace('_', "");
+            // Hexadecimal and binary literals are bit patterns, so they can set the sign bit.
+            let int = match digits.as_rule() {
+                Rule::hex_int => u64::from_str_radix(&text, 16).ok().map(|int| int as i64),
+                Rule::binary_int => u64::from_str_radix(&text, 2).ok().map(|int| int as i64),
+                Rule::decimal_int => text.parse::<i64>().ok(),
                 _ => {
                     return Err(ParserError::internal_grammar_error(
                         source,
                         expression.as_span(),
                     ))
                 }
-            }),
-            span: pest_span_to_range(expression.as_span()).into(),
-        }),
-        Rule::string_l => Expression::Primary(Spanned {
-            inner: Primary::String(expression.as_str().to_string()),
-            span: pest_span_to_range(expression.as_span()).into(),
-        }),
-        Rule::bool_l => Expression::Primary(Spanned {
-            inner: Primary::Bool(match inner.next().unwrap().as_rule() {
+            };
+            let span = pest_span_to_range(expression.as_span());
+            let int = int.ok_or_else(|| ParserError::IntTooLarge {
+                source_code: source.named(),
+                literal: span.clone().into(),
+            })?;
+            Expression::Primary(Spanned::new(source.file, span, Primary::Int(int)))
+        }
+        Rule::float_l => Expression::Primary(Spanned::new(
+            source.file,
+            pest_span_to_range(expression.as_span()),
+            Primary::Float(expression.as_str().parse().unwrap()),
+        )),
+        Rule::string_l => Expression::Primary(Spanned::new(
+            source.file,
+            pest_span_to_range(expression.as_span()),
+            Primary::String(build_string(expression.clone(), source)?),
+        )),
+        Rule::color_l => Expression::Primary(Spanned::new(
+            source.file,
+            pest_span_to_range(expression.as_span()),
+            Primary::Color(parse_hex_color(expression.as_str()).ok_or_else(|| {
+                ParserError::internal_grammar_error(source, expression.as_span())
+            })?),
+        )),
+        Rule::bool_l => Expression::Primary(Spanned::new(
+            source.file,
+            pest_span_to_range(expression.as_span()),
+            Primary::Bool(match inner.next().unwrap().as_rule() {
                 Rule::r#true => true,
                 Rule::r#false => false,
                 _ => {
@@ -254,13 +1421,80 @@ fn build_expression(
                     ))
                 }
             }),
-            span: pest_span_to_range(expression.as_span()).into(),
-        }),
-        Rule::grouping => return Ok(build_next!()),
-        Rule::unit_l => Expression::Primary(Spanned {
-            inner: Primary::Unit,
-            span: pest_span_to_range(expression.as_span()).into(),
+        )),
+        Rule::grouping => unless_1_inner!({
+            let expr = Box::new(build_next!());
+            let annotation = inner.next().unwrap();
+            let span = pest_span_to_range(annotation.as_span());
+            let type_ = build_type(annotation, source, state, false)?;
+            Expression::Annotated { expr, type_: Spanned::new(source.file, span, type_) }
         }),
+        Rule::tuple_l => Expression::Tuple(
+            inner
+                .map(|element| build_expression(element, source, state))
+                .collect::<Result<_, _>>()?,
+        ),
+        Rule::record_l => {
+            let name = inner.next().unwrap();
+            let record = state.intern(name.as_str());
+            let Some(declared) = state.records.get(&record).cloned() else {
+                return Err(ParserError::UnknownType {
+                    source_code: source.named(),
+                    ident: name.as_str().to_string(),
+                    unknown_type: pest_span_to_range(name.as_span()).into(),
+                });
+            };
+
+            let mut fields: Vec<(Spanned<Identifier>, Spanned<Expression>)> = Vec::new();
+            for field in inner {
+                let mut field = field.into_inner();
+                let name = field.next().unwrap();
+                let ident = state.intern(name.as_str());
+                let repeated = fields.iter().any(|(field, _)| field.inner == ident);
+                if !declared.contains(&ident) || repeated {
+                    return Err(ParserError::UnknownField {
+                        source_code: source.named(),
+                        ident: name.as_str().to_string(),
+                        unknown_field: pest_span_to_range(name.as_span()).into(),
+                    });
+                }
+                let name = Spanned::new(source.file, pest_span_to_range(name.as_span()), ident);
+                fields.push((name, build_expression(field.next().unwrap(), source, state)?));
+            }
+
+            let missing: Vec<_> = declared
+                .iter()
+                .filter(|ident| !fields.iter().any(|(field, _)| field.inner == **ident))
+                .filter_map(|ident| state.ident_map.get(ident).cloned())
+                .collect();
+            if !missing.is_empty() {
+                return Err(ParserError::MissingFields {
+                    source_code: source.named(),
+                    fields: missing.join(", "),
+                    this_record: pest_span_to_range(expression.as_span()).into(),
+                });
+            }
+
+            fields.sort_by_key(|(field, _)| {
+                declared.iter().position(|ident| *ident == field.inner)
+            });
+            Expression::Record { name: record, fields }
+        }
+        Rule::array_l => Expression::Array(
+            inner
+                .map(|element| build_expression(element, source, state))
+                .collect::<Result<_, _>>()?,
+        ),
+        Rule::range_l => {
+            let start = Box::new(build_next!());
+            let end = Box::new(build_next!());
+            Expression::Range { start, end }
+        }
+        Rule::unit_l => Expression::Primary(Spanned::new(
+            source.file,
+            pest_span_to_range(expression.as_span()),
+            Primary::Unit,
+        )),
         _ => {
             return Err(ParserError::internal_grammar_error(
                 source,
@@ -270,17 +1504,212 @@ fn build_expression(
     }))
 }
 
-fn build_type(type_: Pair<'_, Rule>, source: &str) -> Result<Type, ParserError> {
+/// Whether `pair` is just the expression inside it, like the rules of the precedence levels that
+/// an expression doesn't use.
+fn only_wraps(pair: &Pair<'_, Rule>) -> bool {
+    let mut inner = pair.clone().into_inner();
+    match pair.as_rule() {
+        Rule::expression | Rule::primary | Rule::literal => true,
+        Rule::r#if
+        | Rule::operation
+        | Rule::unary
+        | Rule::access
+        | Rule::function_application
+        | Rule::lambda
+        | Rule::grouping => inner.len() == 1,
+        Rule::variable => !matches!(
+            inner.next().map(|next| next.as_rule()),
+            Some(Rule::ident | Rule::wildcard)
+        ),
+        _ => false,
+    }
+}
+
+/// Builds a pattern, binding the variables it introduces in the innermost scope.
+fn build_pattern(
+    pattern: Pair<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
+) -> Result<Spanned<Pattern>, ParserError> {
+    let span = pest_span_to_range(pattern.as_span());
+    let text = pattern.as_str();
+    Ok(Spanned::new(
+        source.file,
+        span.clone(),
+        match pattern.as_rule() {
+            Rule::pattern | Rule::pattern_argument | Rule::nested_pattern | Rule::param => {
+                return build_pattern(pattern.into_inner().next().unwrap(), source, state)
+            }
+            // A constructor without arguments is just its name.
+            Rule::constructor_pattern | Rule::type_name => {
+                let mut args = pattern.clone().into_inner();
+                let name = match pattern.as_rule() {
+                    Rule::type_name => pattern,
+                    _ => args.next().unwrap(),
+                };
+                let constructor = state
+                    .resolve(name.as_str())
+                    .filter(|ident| state.constructors.contains_key(ident));
+                let Some(constructor) = constructor else {
+                    return Err(ParserError::UnknownConstructor {
+                        source_code: source.named(),
+                        ident: name.as_str().to_string(),
+                        unknown_constructor: pest_span_to_range(name.as_span()).into(),
+                    });
+                };
+                let args = args
+                    .map(|arg| build_pattern(arg, source, state))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let arity = state.constructors[&constructor];
+                if args.len() != arity {
+                    // Don't underline the whitespace the pattern ends with.
+                    let underlined = span.start..span.start + text.trim_end().len();
+                    return Err(ParserError::ConstructorArityMismatch {
+                        source_code: source.named(),
+                        ident: name.as_str().to_string(),
+                        expected: arity,
+                        found: args.len(),
+                        pattern: underlined.into(),
+                    });
+                }
+                Pattern::Constructor { constructor, args }
+            }
+            Rule::wildcard => Pattern::Wildcard,
+            Rule::ident => {
+                let ident = state.intern(pattern.as_str());
+                state.bind(ident);
+                Pattern::Variable(ident)
+            }
+            Rule::tuple_pattern => Pattern::Tuple(
+                pattern
+                    .into_inner()
+                    .map(|element| build_pattern(element, source, state))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Rule::array_pattern => Pattern::Array(
+                pattern
+                    .into_inner()
+                    .map(|element| build_pattern(element, source, state))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Rule::literal => match build_expression(pattern.clone(), source, state)?.inner {
+                Expression::Primary(primary) => Pattern::Literal(primary.inner),
+                _ => return Err(ParserError::internal_grammar_error(source, pattern.as_span())),
+            },
+            Rule::negative_literal => {
+                let literal = pattern.clone().into_inner().next().unwrap();
+                match build_expression(literal, source, state)?.inner {
+                    Expression::Primary(Spanned { inner: Primary::Int(int), .. }) => {
+                        Pattern::Literal(Primary::Int(int.wrapping_neg()))
+                    }
+                    Expression::Primary(Spanned { inner: Primary::Float(float), .. }) => {
+                        Pattern::Literal(Primary::Float(-float))
+                    }
+                    _ => {
+                        return Err(ParserError::internal_grammar_error(source, pattern.as_span()))
+                    }
+                }
+            }
+            _ => return Err(ParserError::internal_grammar_error(source, pattern.as_span())),
+        },
+    ))
+}
+
+/// Unescapes a `string_l` into the string it denotes.
+fn build_string(string: Pair<'_, Rule>, source: Source<'_>) -> Result<String, ParserError> {
+    let span = string.as_span();
+    let mut unescaped = String::new();
+    for part in string.into_inner() {
+        match part.as_rule() {
+            Rule::string_text => unescaped.push_str(part.as_str()),
+            Rule::escape => {
+                let escaped = &part.as_str()[1..];
+                let character = match escaped {
+                    "n" => Some('\n'),
+                    "t" => Some('\t'),
+                    "r" => Some('\r'),
+                    "0" => Some('\0'),
+                    "\"" => Some('"'),
+                    "\\" => Some('\\'),
+                    _ => escaped
+                        .strip_prefix("u{")
+                        .and_then(|code| code.strip_suffix('}'))
+                        .and_then(|code| u32::from_str_radix(code, 16).ok())
+                        .and_then(char::from_u32),
+                };
+                unescaped.push(character.ok_or_else(|| ParserError::InvalidEscape {
+                    source_code: source.named(),
+                    escape: pest_span_to_range(part.as_span()).into(),
+                })?);
+            }
+            Rule::unterminated => {
+                return Err(ParserError::UnterminatedString {
+                    source_code: source.named(),
+                    string: (span.start()..part.as_span().start()).into(),
+                })
+            }
+            _ => return Err(ParserError::internal_grammar_error(source, part.as_span())),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parses a `#RGB`, `#RRGGBB` or `#RRGGBBAA` sRGB literal into the interpreter's color space.
+fn parse_hex_color(literal: &str) -> Option<Xyza<palette::white_point::D65, f64>> {
+    let digits = literal.strip_prefix('#')?;
+    let digits = match digits.len() {
+        3 => digits.chars().flat_map(|digit| [digit, digit]).chain("FF".chars()).collect(),
+        6 => format!("{digits}FF"),
+        8 => digits.to_string(),
+        _ => return None,
+    };
+    let channel = |index: usize| u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).ok();
+    let srgba = Srgba::new(channel(0)?, channel(1)?, channel(2)?, channel(3)?)
+        .into_format::<f64, f64>();
+    Some(Xyza::from_color(srgba))
+}
+
+/// Builds the type, `variables` being whether it may contain type variables.
+fn build_type(
+    type_: Pair<'_, Rule>,
+    source: Source<'_>,
+    state: &mut ParserState,
+    variables: bool,
+) -> Result<Type, ParserError> {
     let mut inner = type_.clone().into_inner();
     macro_rules! build_next {
         () => {
-            build_type(inner.next().unwrap(), source)?
+            build_type(inner.next().unwrap(), source, state, variables)?
         };
     }
     Ok(match type_.as_rule() {
-        Rule::type_annotation => build_next!(),
+        Rule::type_annotation => {
+            let first = inner.next().unwrap();
+            if first.as_rule() != Rule::context {
+                return build_type(first, source, state, variables);
+            }
+            let mut constraints = Vec::new();
+            for constraint in first.into_inner() {
+                let mut constraint = constraint.into_inner();
+                let name = constraint.next().unwrap();
+                let class = Class::from_name(name.as_str()).ok_or_else(|| {
+                    ParserError::UnknownClass {
+                        source_code: source.named(),
+                        ident: name.as_str().to_string(),
+                        unknown_class: pest_span_to_range(name.as_span()).into(),
+                    }
+                })?;
+                let variable = constraint.next().unwrap();
+                let Type::Var(variable) = build_type(variable, source, state, variables)? else {
+                    unreachable!("constraints are on type variables")
+                };
+                constraints.push((class, variable));
+            }
+            Type::Constrained { constraints, type_: Box::new(build_next!()) }
+        }
         Rule::r#type => build_next!(),
         Rule::base_type => build_next!(),
+        Rule::paren_t => build_next!(),
         Rule::fun_t => {
             let input = Box::new(build_next!());
             let output = Box::new(build_next!());
@@ -288,19 +1717,107 @@ fn build_type(type_: Pair<'_, Rule>, source: &str) -> Result<Type, ParserError>
         }
         Rule::string_t => Type::String,
         Rule::int_t => Type::Int,
+        Rule::float_t => Type::Float,
         Rule::color_t => Type::Color,
         Rule::bool_t => Type::Bool,
         Rule::unit_t => Type::Unit,
         Rule::array_t => Type::Array(Box::new(build_next!())),
+        Rule::tuple_t => Type::Tuple(
+            inner
+                .map(|element| build_type(element, source, state, variables))
+                .collect::<Result<_, _>>()?,
+        ),
+        Rule::named_t => {
+            let name = inner.next().unwrap();
+            let ident = state.intern(name.as_str());
+            if let Some(target) = state.aliases.get(&ident) {
+                return Ok(Type::Alias { ident, target: Box::new(target.clone()) });
+            }
+            if !state.records.contains_key(&ident) && !state.data.contains_key(&ident) {
+                return Err(ParserError::UnknownType {
+                    source_code: source.named(),
+                    ident: name.as_str().to_string(),
+                    unknown_type: pest_span_to_range(name.as_span()).into(),
+                });
+            }
+            Type::Named(ident)
+        }
+        Rule::var_t if variables => Type::Var(state.intern(type_.as_str())),
+        Rule::var_t => {
+            return Err(ParserError::TypeVariableNotAllowed {
+                source_code: source.named(),
+                variable: pest_span_to_range(type_.as_span()).into(),
+            })
+        }
         _ => return Err(ParserError::internal_grammar_error(source, type_.as_span())),
     })
 }
 
+/// Every error found while parsing a file, reported together.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Could not parse the file, found {} error(s)", .errors.len())]
+#[diagnostic(code(easl::parser::errors))]
+pub struct ParserErrors {
+    #[related]
+    pub errors: Vec<ParserError>,
+}
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum ParserError {
-    #[error(transparent)]
-    #[diagnostic(code(easl::parser::pest::pest_error))]
-    PestError(#[from] pest::error::Error<Rule>),
+    #[error("Syntax error")]
+    #[diagnostic(code(easl::parser::syntax_error))]
+    SyntaxError {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("{message}")]
+        at: SourceSpan,
+        message: String,
+    },
+    #[error("The clauses of '{ident}' take different numbers of parameters")]
+    #[diagnostic(
+        code(easl::parser::clause_arity_mismatch),
+        help = "Every clause of a function has to take the same number of parameters"
+    )]
+    ClauseArityMismatch {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        expected: usize,
+        found: usize,
+        #[label("This clause takes {expected}")]
+        first: SourceSpan,
+        #[label("But this one takes {found}")]
+        clause: SourceSpan,
+    },
+    #[error("Line isn't aligned with the `{keyword}` block it's in")]
+    #[diagnostic(
+        code(easl::parser::misaligned_line),
+        help = "Every line of a block has to start at the same column as its first one, lines \
+                indented further than that continue the line above them"
+    )]
+    MisalignedLine {
+        #[source_code]
+        source_code: NamedSource,
+        keyword: &'static str,
+        found: usize,
+        expected: usize,
+        #[label("This line starts at column {found}")]
+        line: SourceSpan,
+        #[label("But the lines of the block start at column {expected}")]
+        block: SourceSpan,
+    },
+    #[error("Unexpected indentation")]
+    #[diagnostic(
+        code(easl::parser::unexpected_indentation),
+        help = "Indented lines continue the statement above them, start the line at the first \
+                column to make it a statement of its own"
+    )]
+    UnexpectedIndentation {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This line is indented")]
+        line: SourceSpan,
+    },
     #[error("Internal grammar error")]
     #[diagnostic(
         code(easl::parser::internal_grammar_error),
@@ -309,37 +1826,316 @@ pub enum ParserError {
     )]
     InternalGrammarError {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
         #[label("Internal grammar error")]
         at: SourceSpan,
     },
     #[error("Identifier defined multiple times")]
     #[diagnostic(
         code(easl::parser::overriden_identifier),
-        help = "Remove one of the definitions"
+        help = "Remove one of the definitions. The clauses of a function have to come right \
+                after each other"
     )]
     OverridenIdentifier {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
+        #[label("Identifier was first assigned here")]
+        first_assignment: SourceSpan,
         #[label("Identifier was assigned again here")]
         second_assignment: SourceSpan,
     },
+    #[error("'{ident}' is defined with {found} parameters but its type only allows {expected}")]
+    #[diagnostic(
+        code(easl::parser::arity_mismatch),
+        help = "Remove the extra parameters or add more arguments to the type ascription"
+    )]
+    ArityMismatch {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        expected: usize,
+        found: usize,
+        #[label("Type ascribed here")]
+        ascription: SourceSpan,
+        #[label("Parameters declared here")]
+        params: SourceSpan,
+    },
     #[error("Unknown identifier '{ident}'")]
     #[diagnostic(code(easl::parser::unknown_identifier), help = "Was this a typo?")]
     UnknownIdentifier {
         #[source_code]
-        source_code: String,
+        source_code: NamedSource,
         ident: String,
         #[label("Unknown identifier")]
         unknown_identifier: SourceSpan,
     },
+    #[error("'{ident}' is used before it's defined")]
+    #[diagnostic(
+        code(easl::parser::local_not_yet_defined),
+        help = "Local definitions can only use the ones before them, so they can't be recursive. \
+            Recursive functions can be defined at the top level"
+    )]
+    LocalNotYetDefined {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Used here")]
+        this_use: SourceSpan,
+        #[label("Defined here")]
+        definition: SourceSpan,
+    },
+    #[error("'_' can only be used in patterns")]
+    #[diagnostic(
+        code(easl::parser::wildcard_expression),
+        help = "A wildcard doesn't bind anything, give the value a name to use it"
+    )]
+    WildcardExpression {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("Used as a value here")]
+        wildcard: SourceSpan,
+    },
+    #[error("'{ident}' is not defined in module '{module}'")]
+    #[diagnostic(code(easl::parser::unknown_import), help = "Was this a typo?")]
+    UnknownImport {
+        #[source_code]
+        source_code: NamedSource,
+        module: String,
+        ident: String,
+        #[label("Imported here")]
+        unknown_import: SourceSpan,
+    },
+    #[error("Unknown constructor '{ident}'")]
+    #[diagnostic(
+        code(easl::parser::unknown_constructor),
+        help = "Constructors have to be declared with `data` before they are matched on"
+    )]
+    UnknownConstructor {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Unknown constructor")]
+        unknown_constructor: SourceSpan,
+    },
+    #[error("Constructor '{ident}' takes {expected} arguments but the pattern has {found}")]
+    #[diagnostic(code(easl::parser::constructor_arity_mismatch))]
+    ConstructorArityMismatch {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        expected: usize,
+        found: usize,
+        #[label("This pattern")]
+        pattern: SourceSpan,
+    },
+    #[error("Unknown type '{ident}'")]
+    #[diagnostic(
+        code(easl::parser::unknown_type),
+        help = "Types have to be declared before they are used"
+    )]
+    UnknownType {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Unknown type")]
+        unknown_type: SourceSpan,
+    },
+    #[error("Type variables can't be used here")]
+    #[diagnostic(
+        code(easl::parser::type_variable_not_allowed),
+        help = "Only type ascriptions can have type variables"
+    )]
+    TypeVariableNotAllowed {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("Type variable")]
+        variable: SourceSpan,
+    },
+    #[error("Unknown class '{ident}'")]
+    #[diagnostic(
+        code(easl::parser::unknown_class),
+        help = "The classes are Eq, Ord, Add, Num, Fractional and Mix"
+    )]
+    UnknownClass {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("Unknown class")]
+        unknown_class: SourceSpan,
+    },
+    #[error("'{ident}' has to be an instance of {superclass} to be an instance of {class}")]
+    #[diagnostic(
+        code(easl::parser::missing_superclass_instance),
+        help = "Declare an instance of {superclass} for '{ident}' before this one"
+    )]
+    MissingSuperclassInstance {
+        #[source_code]
+        source_code: NamedSource,
+        class: &'static str,
+        superclass: &'static str,
+        ident: String,
+        #[label("This instance")]
+        instance: SourceSpan,
+    },
+    #[error("'{ident}' is already an instance of {class}")]
+    #[diagnostic(code(easl::parser::duplicate_instance), help = "Remove one of the instances")]
+    DuplicateInstance {
+        #[source_code]
+        source_code: NamedSource,
+        class: &'static str,
+        ident: String,
+        #[label("Declared again here")]
+        instance: SourceSpan,
+    },
+    #[error("Unknown or repeated method '{ident}' of {class}")]
+    #[diagnostic(
+        code(easl::parser::unknown_method),
+        help = "Every method of the class has to be defined exactly once"
+    )]
+    UnknownMethod {
+        #[source_code]
+        source_code: NamedSource,
+        class: &'static str,
+        ident: String,
+        #[label("This method")]
+        unknown_method: SourceSpan,
+    },
+    #[error("Missing methods {methods}")]
+    #[diagnostic(
+        code(easl::parser::missing_methods),
+        help = "Every method of the class has to be defined exactly once"
+    )]
+    MissingMethods {
+        #[source_code]
+        source_code: NamedSource,
+        methods: String,
+        #[label("In this instance")]
+        this_instance: SourceSpan,
+    },
+    #[error("Field declared multiple times")]
+    #[diagnostic(code(easl::parser::duplicate_field), help = "Rename or remove one of the fields")]
+    DuplicateField {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("Field was first declared here")]
+        first_field: SourceSpan,
+        #[label("Field was declared again here")]
+        second_field: SourceSpan,
+    },
+    #[error("Unknown or repeated field '{ident}'")]
+    #[diagnostic(
+        code(easl::parser::unknown_field),
+        help = "Every field of the record has to be given exactly once"
+    )]
+    UnknownField {
+        #[source_code]
+        source_code: NamedSource,
+        ident: String,
+        #[label("This field")]
+        unknown_field: SourceSpan,
+    },
+    #[error("Missing fields {fields}")]
+    #[diagnostic(
+        code(easl::parser::missing_fields),
+        help = "Every field of the record has to be given exactly once"
+    )]
+    MissingFields {
+        #[source_code]
+        source_code: NamedSource,
+        fields: String,
+        #[label("In this record")]
+        this_record: SourceSpan,
+    },
+    #[error("Unterminated string")]
+    #[diagnostic(
+        code(easl::parser::unterminated_string),
+        help = "Add a closing `\"`. Strings can't span multiple lines, use `\\n` instead"
+    )]
+    UnterminatedString {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This string is never closed")]
+        string: SourceSpan,
+    },
+    #[error("Invalid escape sequence")]
+    #[diagnostic(
+        code(easl::parser::invalid_escape),
+        help = "Valid escapes are \\n, \\t, \\r, \\0, \\\", \\\\ and \\u{{...}}"
+    )]
+    InvalidEscape {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This escape")]
+        escape: SourceSpan,
+    },
+    #[error("Int literal doesn't fit in 64 bits")]
+    #[diagnostic(
+        code(easl::parser::int_too_large),
+        help = "Ints range from -9223372036854775808 to 9223372036854775807, use a Float for \
+                larger numbers"
+    )]
+    IntTooLarge {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This literal")]
+        literal: SourceSpan,
+    },
+    #[error("Ambiguous use of operators with the same precedence")]
+    #[diagnostic(
+        code(easl::parser::ambiguous_operators),
+        help = "Add parentheses to make the grouping explicit"
+    )]
+    AmbiguousOperators {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This operator")]
+        first: SourceSpan,
+        #[label("Can't be chained with this one")]
+        second: SourceSpan,
+    },
+}
+
+/// Problems that don't keep the program from running.
+#[derive(Debug, Error, Diagnostic)]
+pub enum ParserWarning {
+    #[error("Unreachable clause")]
+    #[diagnostic(
+        code(easl::parser::unreachable_clause),
+        severity(Warning),
+        help = "Remove it or move it before the one that matches everything it does"
+    )]
+    UnreachableClause {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This is never used")]
+        unreachable: SourceSpan,
+        #[label("Because this matches everything it does")]
+        covering: SourceSpan,
+    },
 }
 
 impl ParserError {
-    fn internal_grammar_error(source_code: &str, span: pest::Span<'_>) -> Self {
+    /// Converts a pest error for the text starting at `offset` in `source`.
+    pub(crate) fn syntax_error(
+        error: pest::error::Error<Rule>,
+        source: Source<'_>,
+        offset: usize,
+    ) -> Self {
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        Self::SyntaxError {
+            source_code: source.named(),
+            at: (offset + start..offset + end).into(),
+            message: error.variant.message().into_owned(),
+        }
+    }
+
+    fn internal_grammar_error(source_code: Source<'_>, span: pest::Span<'_>) -> Self {
         let span = miette::SourceSpan::new(span.start().into(), (span.end() - span.start()).into());
         Self::InternalGrammarError {
-            source_code: source_code.to_string(),
+            source_code: source_code.named(),
             at: span,
         }
     }
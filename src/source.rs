@@ -0,0 +1,162 @@
+use std::{fmt, ops::Range};
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, Report, SourceCode, SourceSpan};
+use thiserror::Error;
+
+/// A handle to a file in a `SourceDb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// The `include` or `import` the file was first loaded by.
+    pub included_from: Option<(FileId, Range<usize>)>,
+}
+
+/// Owns every file of a program.
+#[derive(Default)]
+pub struct SourceDb {
+    files: Vec<SourceFile>,
+}
+
+impl SourceDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(
+        &mut self,
+        name: String,
+        text: String,
+        included_from: Option<(FileId, Range<usize>)>,
+    ) -> FileId {
+        self.files.push(SourceFile { name, text, included_from });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file.0]
+    }
+
+    pub fn source(&self, file: FileId) -> Source<'_> {
+        let SourceFile { name, text, .. } = self.get(file);
+        Source { file, name, text }
+    }
+
+    /// The file for diagnostics that point into it.
+    pub fn named(&self, file: FileId) -> NamedSource {
+        self.source(file).named()
+    }
+
+    /// Turns `diagnostic` into a report that also points out where the file it happened in
+    /// was included from.
+    pub fn report(&self, diagnostic: impl Diagnostic + Send + Sync + 'static) -> Report {
+        let file = diagnostic_file_name(&diagnostic)
+            .and_then(|name| self.files.iter().position(|file| file.name == name));
+        let mut included_from = Vec::new();
+        let mut file = file.and_then(|file| self.files[file].included_from.clone());
+        while let Some((includer, span)) = file {
+            included_from.push(IncludedFrom {
+                source_code: self.named(includer),
+                include: span.into(),
+            });
+            file = self.get(includer).included_from.clone();
+        }
+        Report::new(WithIncludes { diagnostic: Box::new(diagnostic), included_from })
+    }
+}
+
+/// The name of the file a diagnostic, or the first of its related diagnostics, points into.
+fn diagnostic_file_name(diagnostic: &dyn Diagnostic) -> Option<String> {
+    let own = diagnostic.source_code().zip(diagnostic.labels()).and_then(|(source, labels)| {
+        let span = labels.map(|label| *label.inner()).next()?;
+        let contents = source.read_span(&span, 0, 0).ok()?;
+        contents.name().map(str::to_string)
+    });
+    own.or_else(|| diagnostic.related()?.find_map(diagnostic_file_name))
+}
+
+/// A file's name and text, along with its handle.
+#[derive(Debug, Clone, Copy)]
+pub struct Source<'a> {
+    pub file: FileId,
+    pub name: &'a str,
+    pub text: &'a str,
+}
+
+impl Source<'_> {
+    pub fn named(&self) -> NamedSource {
+        NamedSource::new(self.name, self.text.to_string())
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("Included from here")]
+#[diagnostic(severity(Advice))]
+pub struct IncludedFrom {
+    #[source_code]
+    source_code: NamedSource,
+    #[label("Included here")]
+    include: SourceSpan,
+}
+
+/// A diagnostic followed by the `IncludedFrom`s leading up to its file.
+struct WithIncludes {
+    diagnostic: Box<dyn Diagnostic + Send + Sync>,
+    included_from: Vec<IncludedFrom>,
+}
+
+impl fmt::Debug for WithIncludes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.diagnostic, f)
+    }
+}
+
+impl fmt::Display for WithIncludes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.diagnostic, f)
+    }
+}
+
+impl std::error::Error for WithIncludes {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.diagnostic.source()
+    }
+}
+
+impl Diagnostic for WithIncludes {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic.code()
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        self.diagnostic.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic.help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.diagnostic.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.diagnostic.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        let related = self.diagnostic.related().into_iter().flatten();
+        let included_from = self.included_from.iter().map(|include| include as &dyn Diagnostic);
+        Some(Box::new(related.chain(included_from)))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.diagnostic.diagnostic_source()
+    }
+}
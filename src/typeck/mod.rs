@@ -0,0 +1,1512 @@
+//! Type inference and checking, done before a program is interpreted.
+//!
+//! Every expression gets a key whose type starts out unknown. Combining expressions, like
+//! applying a function or comparing two values, equates their keys with each other or with
+//! concrete types. Equating two keys also equates the types inside them, so this amounts to
+//! Hindley–Milner style unification, which is left to `rusttyc`. Type ascriptions are equated
+//! with the definitions they belong to before any definition is looked at.
+//!
+//! Once a definition has been inferred, the type variables left in its type are generalised:
+//! every use of the definition gets fresh ones, so it can be used with different types. A
+//! definition with an ascription is generic in the type variables written in it instead.
+//!
+//! Type variables can be constrained to the instances of some classes, like the type of a
+//! number literal, which can be any `Num` type. Definitions that aren't functions are only
+//! generic in their unconstrained type variables, and the numbers whose type is still
+//! unknown once everything has been inferred are `Int`s, or `Float`s if they have a
+//! decimal point.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use rusttyc::{
+    Arity, ContextSensitiveVariant, Partial, PreliminaryTypeTable, TcErr, TcKey,
+    VarlessTypeChecker,
+};
+use thiserror::Error;
+
+use crate::parser::ast::{
+    dependency_order, Alternative, BinaryOperator, Builtin, CaseArm, Class, Expression,
+    Identifier, IdentifierMap, NodeId, Pattern, Primary, Spanned, Statement, Type, UnaryOperator,
+};
+use crate::source::{FileId, SourceDb};
+
+/// Where an expression is in the sources.
+type Location = (FileId, Range<usize>);
+
+/// The types inferred for a program.
+#[derive(Debug, Clone)]
+pub struct Typed {
+    /// The type of every top level definition, in the order they are defined in.
+    pub definitions: Vec<(Identifier, Type)>,
+    /// The type of every expression, by the id of its node. Types that couldn't be inferred
+    /// are `Type::Infer`.
+    pub expressions: HashMap<NodeId, Type>,
+}
+
+/// Infers the type of every definition and expression in `statements`, checking them against
+/// their type ascriptions. Type aliases are shown by name in errors unless `expand_aliases` is
+/// set.
+pub fn infer(
+    statements: &[Statement],
+    ident_map: &mut IdentifierMap,
+    sources: &SourceDb,
+    expand_aliases: bool,
+) -> Result<Typed, TypeErrors> {
+    let builtins = Builtin::ALL
+        .iter()
+        .filter_map(|builtin| Some((ident_map.get_from_name(builtin.name())?, *builtin)))
+        .collect();
+    let instances = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Instance { class, type_, .. } => Some((class.inner, type_.inner)),
+            _ => None,
+        })
+        .collect();
+    let mut inference = Inference {
+        checker: VarlessTypeChecker::with_context(instances),
+        ident_map,
+        sources,
+        expand_aliases,
+        globals: HashMap::new(),
+        schemes: HashMap::new(),
+        builtins,
+        scopes: Vec::new(),
+        records: HashMap::new(),
+        constructors: HashMap::new(),
+        ascriptions: HashMap::new(),
+        aliases: HashMap::new(),
+        children: HashMap::new(),
+        type_variables: HashMap::new(),
+        variables: 0,
+        constrained: Vec::new(),
+        generalised: HashSet::new(),
+        expressions: Vec::new(),
+        field_accesses: Vec::new(),
+        unified: 0,
+        budget: None,
+        errors: Vec::new(),
+    };
+    inference.declare(statements);
+
+    for statement in statements {
+        if let Statement::TypeAscription { ident, type_ } = statement {
+            let Some(global) = inference.globals.get(ident).copied() else {
+                continue;
+            };
+            inference.ascriptions.insert(*ident, type_.clone());
+            inference.checked(|inference| {
+                inference.type_variables.clear();
+                let ascription = inference.lift(&type_.inner);
+                inference.unify(ascription, global, type_.file, None, &type_.span)
+            });
+            if let Type::Alias { .. } = type_.inner {
+                inference.aliases.insert(global, type_.inner.clone());
+            }
+            let mut generics = Vec::new();
+            let template = Template::written(&type_.inner, &mut generics);
+            let constraints = match &type_.inner {
+                Type::Constrained { constraints, .. } => constraints.as_slice(),
+                _ => &[],
+            };
+            let generics = generics
+                .into_iter()
+                .map(|generic| Classes::constraining(generic, constraints))
+                .collect();
+            let scheme = Scheme { generics, template };
+            inference.schemes.insert(*ident, scheme);
+        }
+    }
+    // Definitions are inferred after the ones they use, so that those are already generic.
+    // Definitions that use each other are inferred together and generalised at once. Uses of
+    // definitions with an ascription don't count, they are generic from the start.
+    let assignments: Vec<_> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Assignment { ident, expr, .. } => Some((*ident, expr)),
+            _ => None,
+        })
+        .collect();
+    let dependencies: Vec<_> = assignments
+        .iter()
+        .map(|(ident, expr)| {
+            let mut uses = expr.inner.free_variables();
+            uses.retain(|used| !inference.schemes.contains_key(used));
+            (Some(*ident), uses)
+        })
+        .collect();
+    for group in dependency_order(&dependencies) {
+        let group: Vec<_> = group.into_iter().map(|index| assignments[index]).collect();
+        for (ident, expr) in &group {
+            // Point at the ascription when the definition doesn't agree with it.
+            let ascription = inference
+                .ascriptions
+                .get(ident)
+                .filter(|ascription| ascription.file == expr.file)
+                .map(|ascription| ascription.span.clone());
+            inference.checked(|inference| {
+                let global = inference.globals[ident];
+                inference.check(expr, global, ascription.as_ref())
+            });
+        }
+        let unascribed = group
+            .iter()
+            .filter(|(ident, _)| !inference.schemes.contains_key(ident))
+            .map(|(ident, expr)| (*ident, expr.inner.is_lambda()))
+            .collect::<Vec<_>>();
+        inference.generalise(&unascribed);
+    }
+    for statement in statements {
+        if let Statement::Instance { type_, methods, .. } = statement {
+            for (method, expr) in methods {
+                let name = inference.name(&method.inner);
+                let signature = method_type(&name, Type::Named(type_.inner));
+                inference.checked(|inference| {
+                    inference.type_variables.clear();
+                    let expected = inference.lift(&signature);
+                    inference.check(expr, expected, None)
+                });
+            }
+        }
+    }
+    inference.resolve_field_accesses();
+    inference.default();
+
+    let table = inference.solve();
+    let mut expressions = HashMap::new();
+    for (id, (file, span), key) in std::mem::take(&mut inference.expressions) {
+        let Some(type_) = inference.resolve(&table, key) else {
+            // Only the innermost expression is reported, the ones around it are infinite too.
+            let is_infinite = |error: &TypeError| matches!(error, TypeError::InfiniteType { .. });
+            if !inference.errors.iter().any(is_infinite) {
+                inference.errors.push(TypeError::InfiniteType {
+                    source_code: sources.named(file),
+                    expression: span.clone().into(),
+                });
+            }
+            continue;
+        };
+        expressions.insert(id, type_);
+    }
+    let mut definitions = Vec::new();
+    for statement in statements {
+        if let Statement::Assignment { ident, .. } = statement {
+            let type_ = inference.resolve(&table, inference.globals[ident]);
+            definitions.push((*ident, type_.unwrap_or(Type::Infer)));
+        }
+    }
+
+    match inference.errors.is_empty() {
+        true => Ok(Typed { definitions, expressions }),
+        false => Err(TypeErrors { errors: inference.errors }),
+    }
+}
+
+/// The outermost part of a type, the types inside it are the children of its key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Shape {
+    /// Nothing is known about the type yet.
+    Unknown,
+    /// Nothing is known about the type yet either, but it can be told apart from other types
+    /// nothing is known about, and it has to be an instance of some classes. Equated
+    /// variables keep the lowest number.
+    Var(usize, Classes),
+    /// A type variable written in an ascription, which can't become a more specific type.
+    Rigid(Identifier, Classes),
+    String,
+    Int,
+    Float,
+    Color,
+    Bool,
+    Unit,
+    /// The element type is its child.
+    Array,
+    /// The element types are its children.
+    Tuple(usize),
+    Named(Identifier),
+    /// The input and the output type are its children.
+    Fun,
+}
+
+impl ContextSensitiveVariant for Shape {
+    /// The two shapes that couldn't be combined.
+    type Err = (Shape, Shape);
+    /// The instances declared for user declared types.
+    type Context = HashSet<(Class, Identifier)>;
+
+    fn top() -> Self {
+        Shape::Unknown
+    }
+
+    fn meet(
+        lhs: Partial<Self>,
+        rhs: Partial<Self>,
+        instances: &Self::Context,
+    ) -> Result<Partial<Self>, Self::Err> {
+        let variant = match (lhs.variant, rhs.variant) {
+            (Shape::Unknown, other) | (other, Shape::Unknown) => other,
+            (Shape::Var(lhs, lhs_classes), Shape::Var(rhs, rhs_classes)) => {
+                Shape::Var(lhs.min(rhs), lhs_classes.union(rhs_classes))
+            }
+            (Shape::Var(_, classes), other) | (other, Shape::Var(_, classes))
+                if other.is_instance_of(classes, instances) =>
+            {
+                other
+            }
+            (lhs, rhs) if lhs == rhs => lhs,
+            (lhs, rhs) => return Err((lhs, rhs)),
+        };
+        let least_arity = variant.children();
+        Ok(Partial { variant, least_arity })
+    }
+
+    fn arity(&self, _: &Self::Context) -> Arity {
+        Arity::Fixed(self.children())
+    }
+
+    fn equal(this: &Self, that: &Self, _: &Self::Context) -> bool {
+        this == that
+    }
+}
+
+impl Shape {
+    fn children(&self) -> usize {
+        match self {
+            Shape::Array => 1,
+            Shape::Tuple(elements) => *elements,
+            Shape::Fun => 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether the type is an instance of every class in `classes`. Only the outermost part
+    /// of the type is looked at, so `[a -> a]` is an instance of `Eq` as well.
+    fn is_instance_of(&self, classes: Classes, instances: &HashSet<(Class, Identifier)>) -> bool {
+        classes.iter().all(|class| match (self, class) {
+            (Shape::Rigid(_, rigid), class) => rigid.contains(class),
+            (Shape::Fun, _) => false,
+            (_, Class::Eq) => true,
+            (Shape::Named(ident), class) => instances.contains(&(class, *ident)),
+            (Shape::Int, class) => class != Class::Fractional,
+            (Shape::Float, _) => true,
+            (Shape::String, Class::Ord | Class::Add) => true,
+            (Shape::Color, class) => class != Class::Ord,
+            _ => false,
+        })
+    }
+
+    /// Describes the shape for diagnostics, where the types inside it aren't known.
+    fn describe(&self, ident_map: &IdentifierMap) -> String {
+        match self {
+            Shape::Unknown => "an unknown type".to_string(),
+            Shape::Var(_, classes) if classes.is_empty() => "an unknown type".to_string(),
+            Shape::Var(_, classes) if *classes == Classes::of(Class::Num) => "a number".to_string(),
+            Shape::Var(_, classes) if *classes == Classes::of(Class::Fractional) => {
+                "a fractional number".to_string()
+            }
+            Shape::Var(_, classes) => {
+                let classes: Vec<_> = classes.minimal().map(|class| class.name()).collect();
+                format!("an instance of {}", classes.join(" and "))
+            }
+            Shape::String => "String".to_string(),
+            Shape::Int => "Int".to_string(),
+            Shape::Float => "Float".to_string(),
+            Shape::Color => "Color".to_string(),
+            Shape::Bool => "Bool".to_string(),
+            Shape::Unit => "()".to_string(),
+            Shape::Array => "an array".to_string(),
+            Shape::Tuple(elements) => format!("a tuple of {elements}"),
+            Shape::Named(ident) | Shape::Rigid(ident, _) => {
+                ident_map.get(ident).cloned().unwrap_or_default()
+            }
+            Shape::Fun => "a function".to_string(),
+        }
+    }
+}
+
+/// A set of classes, which always has the superclasses of the classes in it as well.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Classes(u8);
+
+impl Classes {
+    fn of(class: Class) -> Classes {
+        let superclasses = class.superclass().map_or(Classes::default(), Classes::of);
+        Classes(1 << class as u8).union(superclasses)
+    }
+
+    /// The classes `variable` is constrained to by `constraints`.
+    fn constraining(variable: Identifier, constraints: &[(Class, Identifier)]) -> Classes {
+        constraints
+            .iter()
+            .filter(|(_, constrained)| *constrained == variable)
+            .fold(Classes::default(), |classes, (class, _)| classes.union(Classes::of(*class)))
+    }
+
+    fn union(self, other: Classes) -> Classes {
+        Classes(self.0 | other.0)
+    }
+
+    fn contains(self, class: Class) -> bool {
+        self.0 & 1 << class as u8 != 0
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn iter(self) -> impl Iterator<Item = Class> {
+        Class::ALL.iter().copied().filter(move |class| self.contains(*class))
+    }
+
+    /// The classes that aren't superclasses of other classes in the set, which are all that
+    /// has to be written.
+    fn minimal(self) -> impl Iterator<Item = Class> {
+        let implied = move |class| self.iter().any(|other| other.superclass() == Some(class));
+        self.iter().filter(move |class| !implied(*class))
+    }
+}
+
+/// The type of a definition that is generic in some type variables, which are replaced with
+/// fresh ones every time the definition is used.
+#[derive(Clone)]
+struct Scheme {
+    /// The classes each type variable it is generic in is constrained to.
+    generics: Vec<Classes>,
+    template: Template,
+}
+
+/// A type to create keys from.
+#[derive(Clone)]
+enum Template {
+    Shape(Shape, Vec<Template>),
+    /// A type without type variables, written in the source.
+    Written(Type),
+    /// The n-th type variable the scheme is generic in.
+    Generic(usize),
+    /// A type shared by every use, because its type variables can't be generalised yet.
+    Fixed(TcKey),
+}
+
+impl Template {
+    /// The template of a type written in an ascription, which is generic in its type
+    /// variables. `generics` are the type variables found so far.
+    fn written(type_: &Type, generics: &mut Vec<Identifier>) -> Template {
+        let (shape, children) = match type_ {
+            Type::Constrained { type_, .. } => return Template::written(type_, generics),
+            Type::Var(ident) => {
+                let index = generics.iter().position(|generic| generic == ident);
+                return Template::Generic(index.unwrap_or_else(|| {
+                    generics.push(*ident);
+                    generics.len() - 1
+                }));
+            }
+            Type::Array(element) => (Shape::Array, vec![element.as_ref()]),
+            Type::Tuple(elements) => (Shape::Tuple(elements.len()), elements.iter().collect()),
+            Type::Fun { input, output } => (Shape::Fun, vec![input.as_ref(), output.as_ref()]),
+            _ => return Template::Written(type_.clone()),
+        };
+        let children: Vec<_> = children
+            .into_iter()
+            .map(|child| Template::written(child, generics))
+            .collect();
+        // Keeps the aliases in types without type variables.
+        match children.iter().all(|child| matches!(child, Template::Written(_))) {
+            true => Template::Written(type_.clone()),
+            false => Template::Shape(shape, children),
+        }
+    }
+
+    /// Collects the keys every use of the template shares.
+    fn fixed(&self, keys: &mut Vec<TcKey>) {
+        match self {
+            Template::Shape(_, children) => children.iter().for_each(|child| child.fixed(keys)),
+            Template::Fixed(key) => keys.push(*key),
+            Template::Written(_) | Template::Generic(_) => {}
+        }
+    }
+}
+
+/// A `record.field` whose record type wasn't known yet when it was inferred.
+#[derive(Clone)]
+struct FieldAccess {
+    record: Spanned<TcKey>,
+    field: Spanned<Identifier>,
+    result: TcKey,
+}
+
+/// Why inferring part of a program failed.
+enum Failure {
+    Error(Box<TypeError>),
+    /// Two types didn't match. Equating them already lost what one of them was, so this is
+    /// turned into an error by `Inference::checked`.
+    Mismatch,
+}
+
+impl From<TypeError> for Failure {
+    fn from(error: TypeError) -> Self {
+        Failure::Error(Box::new(error))
+    }
+}
+
+struct Inference<'a> {
+    checker: VarlessTypeChecker<Shape>,
+    ident_map: &'a mut IdentifierMap,
+    sources: &'a SourceDb,
+    expand_aliases: bool,
+    /// The top level definitions, including constructors.
+    globals: HashMap<Identifier, TcKey>,
+    /// The generalised types of the top level definitions that have been inferred, and of
+    /// the ones with an ascription.
+    schemes: HashMap<Identifier, Scheme>,
+    builtins: HashMap<Identifier, Builtin>,
+    /// Variables bound by lambdas, `let`s and patterns, innermost last.
+    scopes: Vec<HashMap<Identifier, TcKey>>,
+    records: HashMap<Identifier, Vec<(Identifier, Type)>>,
+    /// The `data` type of every constructor and the types of its arguments.
+    constructors: HashMap<Identifier, (Identifier, Vec<Type>)>,
+    ascriptions: HashMap<Identifier, Spanned<Type>>,
+    /// The keys of type aliases written in the source, so errors can show them by name, and
+    /// the keys that have been equated with them.
+    aliases: HashMap<TcKey, Type>,
+    /// The children of the keys created with a shape, and of the keys equated with them.
+    children: HashMap<TcKey, Vec<TcKey>>,
+    /// The keys of the type variables in the ascription being lifted.
+    type_variables: HashMap<Identifier, TcKey>,
+    /// How many `Shape::Var`s have been created.
+    variables: usize,
+    /// The keys of the `Shape::Var`s created constrained to some classes.
+    constrained: Vec<TcKey>,
+    /// The `Shape::Var`s that definitions have been generalised in.
+    generalised: HashSet<usize>,
+    expressions: Vec<(NodeId, Location, TcKey)>,
+    field_accesses: Vec<FieldAccess>,
+    /// How many times `unify` succeeded since `checked` was last called.
+    unified: usize,
+    /// How many more times `unify` may succeed before reporting a mismatch, while `checked`
+    /// replays something that failed.
+    budget: Option<usize>,
+    errors: Vec<TypeError>,
+}
+
+impl Inference<'_> {
+    /// Gives every top level definition a key up front, so that definitions can refer to
+    /// ones further down.
+    fn declare(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Assignment { ident, .. } => {
+                    let key = self.fresh();
+                    self.globals.insert(*ident, key);
+                }
+                Statement::Record { ident, fields } => {
+                    let fields = fields
+                        .iter()
+                        .map(|(field, type_)| (field.inner, type_.inner.clone()))
+                        .collect();
+                    self.records.insert(*ident, fields);
+                }
+                Statement::Data { ident, constructors } => {
+                    for (constructor, types) in constructors {
+                        let types: Vec<_> = types.iter().map(|type_| type_.inner.clone()).collect();
+                        let mut key = self.shape(Shape::Named(*ident), vec![]);
+                        for type_ in types.iter().rev() {
+                            let input = self.lift(type_);
+                            key = self.shape(Shape::Fun, vec![input, key]);
+                        }
+                        self.globals.insert(constructor.inner, key);
+                        self.constructors.insert(constructor.inner, (*ident, types));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Runs `check`, recording the error it fails with.
+    ///
+    /// When two types don't match, `check` is replayed from the types before it ran and
+    /// stopped right before equating them, so that the error can show both.
+    fn checked(&mut self, check: impl Fn(&mut Self) -> Result<(), Failure>) {
+        let snapshot = self.checker.clone();
+        let (aliases, children) = (self.aliases.clone(), self.children.clone());
+        let expressions = self.expressions.len();
+        let field_accesses = self.field_accesses.len();
+        self.unified = 0;
+        let error = match check(self) {
+            Ok(()) => return,
+            Err(Failure::Error(error)) => *error,
+            Err(Failure::Mismatch) => {
+                self.checker = snapshot;
+                (self.aliases, self.children) = (aliases, children);
+                self.expressions.truncate(expressions);
+                self.field_accesses.truncate(field_accesses);
+                self.scopes.clear();
+                self.budget = Some(self.unified);
+                let replayed = check(self);
+                self.budget = None;
+                match replayed {
+                    Err(Failure::Error(error)) => *error,
+                    _ => unreachable!("replaying stops where the types didn't match"),
+                }
+            }
+        };
+        self.errors.push(error);
+        self.scopes.clear();
+    }
+
+    fn expression(&mut self, expression: &Spanned<Expression>) -> Result<TcKey, Failure> {
+        let file = expression.file;
+        let span = &expression.span;
+        let key = match &expression.inner {
+            Expression::If { cond, then, else_ } => {
+                let cond_key = self.expression(cond)?;
+                let bool = self.shape(Shape::Bool, vec![]);
+                self.unify(bool, cond_key, file, None, &cond.span)?;
+                let then_key = self.expression(then)?;
+                let else_key = self.expression(else_)?;
+                self.unify(then_key, else_key, file, Some(&then.span), &else_.span)?;
+                then_key
+            }
+            Expression::Binary { operator, lhs, rhs } => self.binary(operator, lhs, rhs)?,
+            Expression::Unary { operator, rhs } => {
+                let rhs_key = self.expression(rhs)?;
+                let operand = match operator {
+                    UnaryOperator::Not => self.shape(Shape::Bool, vec![]),
+                    UnaryOperator::Negative => self.constrained(Classes::of(Class::Num)),
+                };
+                self.unify(operand, rhs_key, file, None, &rhs.span)?;
+                operand
+            }
+            Expression::FunctionApplication { function, argument } => {
+                let function_key = self.expression(function)?;
+                let argument_key = self.expression(argument)?;
+                let input = self.fresh();
+                let result = self.fresh();
+                let fun = self.shape(Shape::Fun, vec![input, result]);
+                self.unify(fun, function_key, file, Some(span), &function.span)?;
+                self.unify(input, argument_key, file, Some(&function.span), &argument.span)?;
+                result
+            }
+            Expression::Let { bindings, body } => {
+                self.scopes.push(HashMap::new());
+                self.bindings(bindings)?;
+                let body = self.expression(body)?;
+                self.scopes.pop();
+                body
+            }
+            Expression::Case { scrutinee, arms } => {
+                let scrutinee_key = self.expression(scrutinee)?;
+                let result = self.fresh();
+                let first_body = arms
+                    .first()
+                    .and_then(|arm| arm.alternatives.first())
+                    .map(|alternative| &alternative.body.span);
+                for CaseArm { pattern, bindings, alternatives } in arms {
+                    self.scopes.push(HashMap::new());
+                    let pattern_key = self.pattern(pattern)?;
+                    let scrutinee_at = Some(&scrutinee.span);
+                    self.unify(scrutinee_key, pattern_key, file, scrutinee_at, &pattern.span)?;
+                    self.bindings(bindings)?;
+                    for Alternative { guard, body } in alternatives {
+                        if let Some(guard) = guard {
+                            let guard_key = self.expression(guard)?;
+                            let bool = self.shape(Shape::Bool, vec![]);
+                            self.unify(bool, guard_key, file, None, &guard.span)?;
+                        }
+                        let body_key = self.expression(body)?;
+                        self.unify(result, body_key, file, first_body, &body.span)?;
+                    }
+                    self.scopes.pop();
+                }
+                result
+            }
+            Expression::Array(elements) => {
+                let element = self.fresh();
+                let first = elements.first().map(|element| &element.span);
+                for expression in elements {
+                    let key = self.expression(expression)?;
+                    self.unify(element, key, file, first, &expression.span)?;
+                }
+                self.shape(Shape::Array, vec![element])
+            }
+            Expression::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.expression(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.shape(Shape::Tuple(elements.len()), elements)
+            }
+            Expression::Record { name, fields } => {
+                let declared = self.records.get(name).cloned().unwrap_or_default();
+                for ((_, value), (_, type_)) in fields.iter().zip(declared) {
+                    let value_key = self.expression(value)?;
+                    let field = self.lift(&type_);
+                    self.unify(field, value_key, file, None, &value.span)?;
+                }
+                self.shape(Shape::Named(*name), vec![])
+            }
+            Expression::FieldAccess { record, field } => {
+                let record_key = self.expression(record)?;
+                let result = self.fresh();
+                let access = FieldAccess {
+                    record: Spanned::new(file, record.span.clone(), record_key),
+                    field: field.clone(),
+                    result,
+                };
+                let candidates = self.records_with(field.inner);
+                match candidates.as_slice() {
+                    [] => {
+                        return Err(TypeError::UnknownField {
+                            source_code: self.sources.named(file),
+                            field: self.name(&field.inner),
+                            this_field: field.span.clone().into(),
+                        }
+                        .into())
+                    }
+                    [record_type] => self.access(*record_type, &access)?,
+                    // Which record it is will hopefully be known once the rest of the
+                    // program has been looked at.
+                    _ => self.field_accesses.push(access),
+                }
+                result
+            }
+            Expression::Range { start, end } => {
+                for bound in [start, end] {
+                    let key = self.expression(bound)?;
+                    let int = self.shape(Shape::Int, vec![]);
+                    self.unify(int, key, file, None, &bound.span)?;
+                }
+                let int = self.shape(Shape::Int, vec![]);
+                self.shape(Shape::Array, vec![int])
+            }
+            Expression::Annotated { expr, type_ } => {
+                let annotation = self.lift(&type_.inner);
+                self.check(expr, annotation, Some(&type_.span))?;
+                annotation
+            }
+            Expression::Variable(ident) => self.variable(ident),
+            Expression::Primary(primary) => self.primary(primary)?,
+            Expression::Error => self.fresh(),
+        };
+        self.expressions.push((expression.id, (file, span.clone()), key));
+        Ok(key)
+    }
+
+    /// Binds the patterns of a `let`, or of the `where` clause of a case arm, in the innermost
+    /// scope, each one in scope for the ones after it.
+    fn bindings(
+        &mut self,
+        bindings: &[(Spanned<Pattern>, Spanned<Expression>)],
+    ) -> Result<(), Failure> {
+        for (pattern, value) in bindings {
+            let value_key = self.expression(value)?;
+            let pattern_key = self.pattern(pattern)?;
+            let file = value.file;
+            self.unify(pattern_key, value_key, file, Some(&pattern.span), &value.span)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `expression` has the `expected` type. The parameters of a lambda get their
+    /// types from `expected` before its body is inferred, so a body that doesn't match is
+    /// reported instead of the whole lambda.
+    fn check(
+        &mut self,
+        expression: &Spanned<Expression>,
+        expected: TcKey,
+        expected_at: Option<&Range<usize>>,
+    ) -> Result<(), Failure> {
+        let file = expression.file;
+        let span = &expression.span;
+        let Expression::Primary(Spanned { inner: Primary::Lambda { param, body }, .. }) =
+            &expression.inner
+        else {
+            let key = self.expression(expression)?;
+            return self.unify(expected, key, file, expected_at, span);
+        };
+        let param = self.bind(param)?;
+        let result = self.fresh();
+        let fun = self.shape(Shape::Fun, vec![param, result]);
+        self.unify(expected, fun, file, expected_at, span)?;
+        self.check(body, result, expected_at)?;
+        self.scopes.pop();
+        self.expressions.push((expression.id, (file, span.clone()), fun));
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        operator: &BinaryOperator,
+        lhs: &Spanned<Expression>,
+        rhs: &Spanned<Expression>,
+    ) -> Result<TcKey, Failure> {
+        let file = lhs.file;
+        let lhs_key = self.expression(lhs)?;
+        let rhs_key = self.expression(rhs)?;
+        if let BinaryOperator::Index = operator {
+            let element = self.fresh();
+            let array = self.shape(Shape::Array, vec![element]);
+            self.unify(array, lhs_key, file, None, &lhs.span)?;
+            let int = self.shape(Shape::Int, vec![]);
+            self.unify(int, rhs_key, file, None, &rhs.span)?;
+            return Ok(element);
+        }
+        // What both operands have to be, the right one is also expected to be like the left.
+        let operand = match operator.class() {
+            Some(class) => self.constrained(Classes::of(class)),
+            None => self.shape(Shape::Int, vec![]),
+        };
+        self.unify(operand, lhs_key, file, None, &lhs.span)?;
+        self.unify(lhs_key, rhs_key, file, Some(&lhs.span), &rhs.span)?;
+        Ok(match operator {
+            BinaryOperator::Add
+            | BinaryOperator::Sub
+            | BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::Remainder
+            | BinaryOperator::BitAnd
+            | BinaryOperator::BitOr
+            | BinaryOperator::BitXor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight => lhs_key,
+            _ => self.shape(Shape::Bool, vec![]),
+        })
+    }
+
+    fn variable(&mut self, ident: &Identifier) -> TcKey {
+        let local = self.scopes.iter().rev().find_map(|scope| scope.get(ident));
+        if let Some(key) = local {
+            return *key;
+        }
+        if let Some(scheme) = self.schemes.get(ident).cloned() {
+            return self.instantiate(&scheme);
+        }
+        if let Some(key) = self.globals.get(ident) {
+            return *key;
+        }
+        match self.builtins.get(ident) {
+            Some(builtin) => self.builtin(*builtin),
+            // Only happens for programs with parser errors.
+            None => self.fresh(),
+        }
+    }
+
+    /// The type of a builtin, with fresh keys for the types it works with any of.
+    fn builtin(&mut self, builtin: Builtin) -> TcKey {
+        let fun = |inference: &mut Self, types: &[TcKey]| {
+            let (output, inputs) = types.split_last().unwrap();
+            inputs.iter().rev().fold(*output, |output, input| {
+                inference.shape(Shape::Fun, vec![*input, output])
+            })
+        };
+        let int = self.shape(Shape::Int, vec![]);
+        let float = self.shape(Shape::Float, vec![]);
+        let color = self.shape(Shape::Color, vec![]);
+        let a = self.fresh();
+        let b = self.fresh();
+        let array_a = self.shape(Shape::Array, vec![a]);
+        match builtin {
+            // [a] -> Int
+            Builtin::Length => fun(self, &[array_a, int]),
+            // (a -> b) -> [a] -> [b]
+            Builtin::Map => {
+                let function = fun(self, &[a, b]);
+                let array_b = self.shape(Shape::Array, vec![b]);
+                fun(self, &[function, array_a, array_b])
+            }
+            // (b -> a -> b) -> b -> [a] -> b
+            Builtin::Fold => {
+                let function = fun(self, &[b, a, b]);
+                fun(self, &[function, b, array_a, b])
+            }
+            // Float -> Float -> Float -> Color
+            Builtin::Rgb | Builtin::Hsv | Builtin::Cmy | Builtin::Xyz => {
+                fun(self, &[float, float, float, color])
+            }
+            // Float -> Color -> Color
+            Builtin::Alpha => fun(self, &[float, color, color]),
+            // Mix a => Float -> a -> a -> a
+            Builtin::Mix => {
+                let a = self.constrained(Classes::of(Class::Mix));
+                fun(self, &[float, a, a, a])
+            }
+            // Num a => a -> a
+            Builtin::Negate => {
+                let a = self.constrained(Classes::of(Class::Num));
+                fun(self, &[a, a])
+            }
+            // Int -> Float
+            Builtin::ToFloat => fun(self, &[int, float]),
+            // Float -> Int
+            Builtin::Floor | Builtin::Round => fun(self, &[float, int]),
+            // Bool
+            Builtin::Otherwise => self.shape(Shape::Bool, vec![]),
+        }
+    }
+
+    fn primary(&mut self, primary: &Spanned<Primary>) -> Result<TcKey, Failure> {
+        Ok(match &primary.inner {
+            Primary::Lambda { param, body } => {
+                let param = self.bind(param)?;
+                let body = self.expression(body)?;
+                self.scopes.pop();
+                self.shape(Shape::Fun, vec![param, body])
+            }
+            primary => self.value(primary),
+        })
+    }
+
+    /// The type of a value that isn't a function.
+    fn value(&mut self, value: &Primary) -> TcKey {
+        match value {
+            Primary::String(_) => self.shape(Shape::String, vec![]),
+            Primary::Int(_) => self.constrained(Classes::of(Class::Num)),
+            Primary::Float(_) => self.constrained(Classes::of(Class::Fractional)),
+            Primary::Bool(_) => self.shape(Shape::Bool, vec![]),
+            Primary::Color(_) => self.shape(Shape::Color, vec![]),
+            Primary::Unit => self.shape(Shape::Unit, vec![]),
+            Primary::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(|element| self.value(element)).collect();
+                self.shape(Shape::Tuple(elements.len()), elements)
+            }
+            Primary::Record { name, .. } => self.shape(Shape::Named(*name), vec![]),
+            Primary::Constructor { data, arity, args, .. } if args.len() == *arity => {
+                self.shape(Shape::Named(*data), vec![])
+            }
+            // Only produced by the interpreter.
+            _ => self.fresh(),
+        }
+    }
+
+    /// Infers the type of a pattern, binding the variables in it in the innermost scope.
+    fn pattern(&mut self, pattern: &Spanned<Pattern>) -> Result<TcKey, Failure> {
+        Ok(match &pattern.inner {
+            Pattern::Wildcard => self.fresh(),
+            Pattern::Variable(ident) => {
+                let key = self.fresh();
+                self.scopes.last_mut().unwrap().insert(*ident, key);
+                key
+            }
+            Pattern::Literal(literal) => self.value(literal),
+            Pattern::Tuple(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.pattern(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.shape(Shape::Tuple(elements.len()), elements)
+            }
+            Pattern::Array(elements) => {
+                let element = self.fresh();
+                let first = elements.first().map(|element| &element.span);
+                for pattern in elements {
+                    let key = self.pattern(pattern)?;
+                    self.unify(element, key, pattern.file, first, &pattern.span)?;
+                }
+                self.shape(Shape::Array, vec![element])
+            }
+            Pattern::Constructor { constructor, args } => {
+                let (data, types) = self.constructors[constructor].clone();
+                for (arg, type_) in args.iter().zip(&types) {
+                    let arg_key = self.pattern(arg)?;
+                    let type_ = self.lift(type_);
+                    self.unify(type_, arg_key, pattern.file, None, &arg.span)?;
+                }
+                self.shape(Shape::Named(data), vec![])
+            }
+        })
+    }
+
+    /// Binds the variables of a lambda's `param` in a new scope.
+    fn bind(&mut self, param: &Spanned<Pattern>) -> Result<TcKey, Failure> {
+        self.scopes.push(HashMap::new());
+        self.pattern(param)
+    }
+
+    /// The records that declare `field`.
+    fn records_with(&self, field: Identifier) -> Vec<Identifier> {
+        let mut records: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, fields)| fields.iter().any(|(name, _)| *name == field))
+            .map(|(record, _)| *record)
+            .collect();
+        records.sort_by_key(|record| record.handle);
+        records
+    }
+
+    /// Infers a field access once it's known which record is accessed.
+    fn access(&mut self, record: Identifier, access: &FieldAccess) -> Result<(), Failure> {
+        let file = access.record.file;
+        let Some((_, type_)) = self.records[&record]
+            .iter()
+            .find(|(name, _)| *name == access.field.inner)
+            .cloned()
+        else {
+            return Err(TypeError::NoSuchField {
+                source_code: self.sources.named(file),
+                field: self.name(&access.field.inner),
+                type_: self.name(&record),
+                this_field: access.field.span.clone().into(),
+            }
+            .into());
+        };
+        let record = self.shape(Shape::Named(record), vec![]);
+        self.unify(record, access.record.inner, file, None, &access.record.span)?;
+        let field = self.lift(&type_);
+        self.unify(field, access.result, file, None, &access.field.span)
+    }
+
+    /// Infers the field accesses that could be on several records, now that more is known
+    /// about the types of the records accessed.
+    fn resolve_field_accesses(&mut self) {
+        while !self.field_accesses.is_empty() {
+            let table = self.solve();
+            let pending = std::mem::take(&mut self.field_accesses);
+            let count = pending.len();
+            for access in pending {
+                match &table[&access.record.inner].variant {
+                    Shape::Unknown | Shape::Var(..) => self.field_accesses.push(access),
+                    Shape::Named(record) if self.records.contains_key(record) => {
+                        let record = *record;
+                        self.checked(|inference| inference.access(record, &access));
+                    }
+                    shape => self.errors.push(TypeError::NoSuchField {
+                        source_code: self.sources.named(access.record.file),
+                        field: self.name(&access.field.inner),
+                        type_: shape.describe(&*self.ident_map),
+                        this_field: access.field.span.into(),
+                    }),
+                }
+            }
+            if self.field_accesses.len() == count {
+                for access in std::mem::take(&mut self.field_accesses) {
+                    self.errors.push(TypeError::AmbiguousField {
+                        source_code: self.sources.named(access.record.file),
+                        field: self.name(&access.field.inner),
+                        this_field: access.field.span.into(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Creates a key for the type written in the source.
+    fn lift(&mut self, type_: &Type) -> TcKey {
+        match type_ {
+            Type::Infer => self.fresh(),
+            Type::String => self.shape(Shape::String, vec![]),
+            Type::Int => self.shape(Shape::Int, vec![]),
+            Type::Float => self.shape(Shape::Float, vec![]),
+            Type::Color => self.shape(Shape::Color, vec![]),
+            Type::Bool => self.shape(Shape::Bool, vec![]),
+            Type::Unit => self.shape(Shape::Unit, vec![]),
+            Type::Array(element) => {
+                let element = self.lift(element);
+                self.shape(Shape::Array, vec![element])
+            }
+            Type::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(|element| self.lift(element)).collect();
+                self.shape(Shape::Tuple(elements.len()), elements)
+            }
+            Type::Named(ident) => self.shape(Shape::Named(*ident), vec![]),
+            Type::Alias { target, .. } => {
+                let key = self.lift(target);
+                self.aliases.insert(key, type_.clone());
+                key
+            }
+            Type::Fun { input, output } => {
+                let input = self.lift(input);
+                let output = self.lift(output);
+                self.shape(Shape::Fun, vec![input, output])
+            }
+            Type::Var(ident) => match self.type_variables.get(ident) {
+                Some(key) => *key,
+                None => {
+                    let key = self.shape(Shape::Rigid(*ident, Classes::default()), vec![]);
+                    self.type_variables.insert(*ident, key);
+                    key
+                }
+            },
+            Type::Constrained { constraints, type_ } => {
+                for (_, variable) in constraints {
+                    if !self.type_variables.contains_key(variable) {
+                        let classes = Classes::constraining(*variable, constraints);
+                        let key = self.shape(Shape::Rigid(*variable, classes), vec![]);
+                        self.type_variables.insert(*variable, key);
+                    }
+                }
+                self.lift(type_)
+            }
+        }
+    }
+
+    /// Creates a key for a type nothing is known about.
+    fn fresh(&mut self) -> TcKey {
+        self.constrained(Classes::default())
+    }
+
+    /// Creates a key for a type that has to be an instance of `classes`.
+    fn constrained(&mut self, classes: Classes) -> TcKey {
+        self.variables += 1;
+        let key = self.shape(Shape::Var(self.variables, classes), vec![]);
+        if !classes.is_empty() {
+            self.constrained.push(key);
+        }
+        key
+    }
+
+    /// Creates a key for a use of a definition with the type `scheme`.
+    fn instantiate(&mut self, scheme: &Scheme) -> TcKey {
+        let generics: Vec<_> =
+            scheme.generics.iter().map(|classes| self.constrained(*classes)).collect();
+        self.instantiate_template(&scheme.template, &generics)
+    }
+
+    fn instantiate_template(&mut self, template: &Template, generics: &[TcKey]) -> TcKey {
+        match template {
+            Template::Shape(shape, children) => {
+                let children = children
+                    .iter()
+                    .map(|child| self.instantiate_template(child, generics))
+                    .collect();
+                self.shape(shape.clone(), children)
+            }
+            Template::Written(type_) => self.lift(type_),
+            Template::Generic(index) => generics[*index],
+            Template::Fixed(key) => *key,
+        }
+    }
+
+    /// Generalises the types of a `group` of top level definitions that use each other now
+    /// that they've been inferred, along with whether each one is a function. Type variables
+    /// that the types of other definitions, or field accesses that haven't been inferred yet,
+    /// depend on stay the same for every use. So do the constrained ones of definitions that
+    /// aren't a function, which are values computed only once.
+    fn generalise(&mut self, group: &[(Identifier, bool)]) {
+        if group.is_empty() {
+            return;
+        }
+        let table = self.solve();
+        let mut fixed = Vec::new();
+        let in_group = |global: &Identifier| group.iter().any(|(ident, _)| ident == global);
+        let pending = self
+            .globals
+            .iter()
+            .filter(|(global, _)| !in_group(global) && !self.schemes.contains_key(global))
+            .map(|(_, key)| *key);
+        let accesses = self.field_accesses.iter();
+        let accessed = accesses.flat_map(|access| [access.record.inner, access.result]);
+        let mut shared = Vec::new();
+        for scheme in self.schemes.values() {
+            scheme.template.fixed(&mut shared);
+        }
+        for key in pending.chain(accessed).chain(shared) {
+            self.variables_in(&table, key, &mut fixed, &mut Vec::new());
+        }
+        // A value in the group shares its constrained variables with the rest of it.
+        for (ident, function) in group {
+            if !function {
+                let mut variables = Vec::new();
+                self.variables_in(&table, self.globals[ident], &mut variables, &mut Vec::new());
+                let constrained = |variable: &Shape| !matches!(variable, Shape::Var(_, classes)
+                    if classes.is_empty());
+                fixed.extend(variables.into_iter().filter(constrained));
+            }
+        }
+        let fixed: HashSet<_> = fixed.into_iter().collect();
+
+        let mut schemes = Vec::new();
+        for (ident, _) in group {
+            let mut generics = Vec::new();
+            let key = self.globals[ident];
+            // Infinite types have been reported already.
+            let Some(template) =
+                self.template(&table, key, &fixed, &mut generics, &mut Vec::new())
+            else {
+                continue;
+            };
+            let generics = generics
+                .into_iter()
+                .map(|generic| match generic {
+                    Shape::Var(variable, classes) => {
+                        self.generalised.insert(variable);
+                        classes
+                    }
+                    Shape::Rigid(_, classes) => classes,
+                    _ => unreachable!("only type variables are generalised"),
+                })
+                .collect();
+            schemes.push((*ident, Scheme { generics, template }));
+        }
+        self.schemes.extend(schemes);
+    }
+
+    /// Makes the numbers whose type couldn't be inferred `Int`s, or `Float`s if they have
+    /// to be `Fractional`. Between them they are instances of every class.
+    fn default(&mut self) {
+        let table = self.solve();
+        for key in std::mem::take(&mut self.constrained) {
+            if let Shape::Var(variable, classes) = table[&key].variant {
+                if !self.generalised.contains(&variable) {
+                    let shape = match classes.contains(Class::Fractional) {
+                        true => Shape::Float,
+                        false => Shape::Int,
+                    };
+                    let default = self.shape(shape, vec![]);
+                    let equated = self.checker.impose(default.equate_with(key));
+                    equated.expect("the default is always an instance");
+                }
+            }
+        }
+    }
+
+    /// The template of the type of `key`, generic in the type variables not in `fixed`.
+    /// `generics` are the type variables found so far, `visiting` the types `key` is in.
+    fn template(
+        &self,
+        table: &PreliminaryTypeTable<Shape>,
+        key: TcKey,
+        fixed: &HashSet<Shape>,
+        generics: &mut Vec<Shape>,
+        visiting: &mut Vec<TcKey>,
+    ) -> Option<Template> {
+        if let Some(alias) = self.aliases.get(&key) {
+            return Some(Template::Written(alias.clone()));
+        }
+        let preliminary = &table[&key];
+        match &preliminary.variant {
+            shape @ (Shape::Var(..) | Shape::Rigid(..)) if !fixed.contains(shape) => {
+                let index = generics.iter().position(|generic| generic == shape);
+                return Some(Template::Generic(index.unwrap_or_else(|| {
+                    generics.push(shape.clone());
+                    generics.len() - 1
+                })));
+            }
+            Shape::Unknown | Shape::Var(..) | Shape::Rigid(..) => return Some(Template::Fixed(key)),
+            _ => {}
+        }
+        if self.contains_itself(table, key, visiting) {
+            return None;
+        }
+        visiting.push(key);
+        let mut children = Vec::new();
+        for child in &preliminary.children {
+            children.push(self.template(table, (*child)?, fixed, generics, visiting)?);
+        }
+        visiting.pop();
+        Some(Template::Shape(preliminary.variant.clone(), children))
+    }
+
+    fn shape(&mut self, shape: Shape, children: Vec<TcKey>) -> TcKey {
+        let key = self.checker.lift_into(shape, children.clone());
+        if !children.is_empty() {
+            self.children.insert(key, children);
+        }
+        key
+    }
+
+    /// Equates the type of `found`, the expression at `found_at`, with the type it's expected
+    /// to have, because of the expression at `expected_at` if there is one.
+    fn unify(
+        &mut self,
+        expected: TcKey,
+        found: TcKey,
+        file: FileId,
+        expected_at: Option<&Range<usize>>,
+        found_at: &Range<usize>,
+    ) -> Result<(), Failure> {
+        // Like `x + x`, which rusttyc doesn't allow to be equated.
+        if expected == found {
+            return Ok(());
+        }
+        if self.budget == Some(0) {
+            let table = self.solve();
+            return Err(TypeError::Mismatch {
+                source_code: self.sources.named(file),
+                expected: self.display(&table, expected),
+                found: self.display(&table, found),
+                expected_at: expected_at.map(|span| span.clone().into()),
+                found_at: found_at.clone().into(),
+            }
+            .into());
+        }
+        match self.checker.impose(expected.equate_with(found)) {
+            Ok(()) => {
+                self.unified += 1;
+                if let Some(budget) = &mut self.budget {
+                    *budget -= 1;
+                }
+                self.share_aliases(expected, found);
+                Ok(())
+            }
+            Err(TcErr::KeyEquation(..) | TcErr::Bound(..)) => Err(Failure::Mismatch),
+            Err(error) => unreachable!("every shape has a fixed number of children: {error:?}"),
+        }
+    }
+
+    /// Gives keys that were just equated, and their children, the aliases of each other, so
+    /// that copies of a type made at its uses, like the parameter and result of a function
+    /// at a call, are shown by the alias too.
+    fn share_aliases(&mut self, expected: TcKey, found: TcKey) {
+        // Infinite types are among their own children, so every pair is only visited once.
+        let mut pairs = vec![(expected, found)];
+        let mut visited = HashSet::new();
+        while let Some((a, b)) = pairs.pop() {
+            if a == b || !visited.insert((a, b)) {
+                continue;
+            }
+            match (self.aliases.get(&a), self.aliases.get(&b)) {
+                (Some(alias), None) => _ = self.aliases.insert(b, alias.clone()),
+                (None, Some(alias)) => _ = self.aliases.insert(a, alias.clone()),
+                _ => {}
+            }
+            match (self.children.get(&a).cloned(), self.children.get(&b).cloned()) {
+                (Some(a_children), Some(b_children)) => {
+                    pairs.extend(a_children.into_iter().zip(b_children));
+                }
+                (Some(children), None) => _ = self.children.insert(b, children),
+                (None, Some(children)) => _ = self.children.insert(a, children),
+                (None, None) => {}
+            }
+        }
+    }
+
+    /// The types known so far.
+    fn solve(&self) -> PreliminaryTypeTable<Shape> {
+        // Nothing but equalities is ever imposed, so solving can't fail.
+        self.checker.clone().type_check_preliminary().expect("only keys are equated")
+    }
+
+    /// Whether the type of `key` is one of the types it is inside of, `visiting`.
+    fn contains_itself(
+        &self,
+        table: &PreliminaryTypeTable<Shape>,
+        key: TcKey,
+        visiting: &[TcKey],
+    ) -> bool {
+        let preliminary = &table[&key];
+        // Keys of the same type share their children, so running into a type with the same
+        // shape and children as one it is inside of means it contains itself.
+        !preliminary.children.is_empty()
+            && visiting.iter().any(|outer| {
+                let outer = &table[outer];
+                outer.variant == preliminary.variant && outer.children == preliminary.children
+            })
+    }
+
+    /// Collects the type variables in the type of `key` into `found`, in the order they
+    /// appear in. Returns `false` if the type is infinite.
+    fn variables_in(
+        &self,
+        table: &PreliminaryTypeTable<Shape>,
+        key: TcKey,
+        found: &mut Vec<Shape>,
+        visiting: &mut Vec<TcKey>,
+    ) -> bool {
+        if self.aliases.contains_key(&key) {
+            return true;
+        }
+        let preliminary = &table[&key];
+        if let shape @ (Shape::Var(..) | Shape::Rigid(..)) = &preliminary.variant {
+            if !found.contains(shape) {
+                found.push(shape.clone());
+            }
+            return true;
+        }
+        if self.contains_itself(table, key, visiting) {
+            return false;
+        }
+        visiting.push(key);
+        let finite = preliminary.children.iter().flatten().all(|child| {
+            self.variables_in(table, *child, found, visiting)
+        });
+        visiting.pop();
+        finite
+    }
+
+    /// Turns the type of `key` into a `Type`, or `None` if it's infinite. Type variables
+    /// that weren't written in the source are named in the order they appear in.
+    fn resolve(&mut self, table: &PreliminaryTypeTable<Shape>, key: TcKey) -> Option<Type> {
+        let mut variables = Vec::new();
+        if !self.variables_in(table, key, &mut variables, &mut Vec::new()) {
+            return None;
+        }
+        let written: Vec<_> = variables
+            .iter()
+            .filter_map(|variable| match variable {
+                Shape::Rigid(ident, _) => Some(self.name(ident)),
+                _ => None,
+            })
+            .collect();
+        let mut names = (0..).map(variable_name).filter(|name| !written.contains(name));
+        let mut named = HashMap::new();
+        let mut constraints = Vec::new();
+        for variable in variables {
+            let (ident, classes) = match variable {
+                Shape::Var(_, classes) => {
+                    let name = names.next().unwrap();
+                    let ident = self.ident_map.create_identifier(name);
+                    let ident = ident.unwrap_or_else(|ident| ident);
+                    named.insert(variable, ident);
+                    (ident, classes)
+                }
+                Shape::Rigid(ident, classes) => (ident, classes),
+                _ => continue,
+            };
+            constraints.extend(classes.minimal().map(|class| (class, ident)));
+        }
+        let type_ = self.type_of(table, key, &named);
+        Some(match constraints.is_empty() {
+            true => type_,
+            false => Type::Constrained { constraints, type_: Box::new(type_) },
+        })
+    }
+
+    /// Turns the finite type of `key` into a `Type`, `named` being the names of its
+    /// `Shape::Var`s.
+    fn type_of(
+        &self,
+        table: &PreliminaryTypeTable<Shape>,
+        key: TcKey,
+        named: &HashMap<Shape, Identifier>,
+    ) -> Type {
+        if let Some(alias) = self.aliases.get(&key) {
+            return alias.clone();
+        }
+        let preliminary = &table[&key];
+        let mut children = preliminary.children.iter().map(|child| match child {
+            Some(child) => self.type_of(table, *child, named),
+            None => Type::Infer,
+        });
+        match &preliminary.variant {
+            Shape::Unknown => Type::Infer,
+            shape @ Shape::Var(..) => named.get(shape).copied().map_or(Type::Infer, Type::Var),
+            Shape::Rigid(ident, _) => Type::Var(*ident),
+            Shape::String => Type::String,
+            Shape::Int => Type::Int,
+            Shape::Float => Type::Float,
+            Shape::Color => Type::Color,
+            Shape::Bool => Type::Bool,
+            Shape::Unit => Type::Unit,
+            Shape::Array => Type::Array(Box::new(children.next().unwrap())),
+            Shape::Tuple(_) => Type::Tuple(children.collect()),
+            Shape::Named(ident) => Type::Named(*ident),
+            Shape::Fun => Type::Fun {
+                input: Box::new(children.next().unwrap()),
+                output: Box::new(children.next().unwrap()),
+            },
+        }
+    }
+
+    /// Shows the type of `key` in an error.
+    fn display(&mut self, table: &PreliminaryTypeTable<Shape>, key: TcKey) -> String {
+        if let shape @ Shape::Var(..) = &table[&key].variant {
+            return shape.describe(self.ident_map);
+        }
+        match self.resolve(table, key) {
+            Some(type_) => type_.display(self.ident_map, self.expand_aliases).to_string(),
+            None => "an infinite type".to_string(),
+        }
+    }
+
+    fn name(&self, ident: &Identifier) -> String {
+        self.ident_map.get(ident).cloned().unwrap_or_default()
+    }
+}
+
+/// The type the method `name` of a class has in the instance for `type_`.
+fn method_type(name: &str, type_: Type) -> Type {
+    let fun = |input: Type, output: Type| Type::Fun {
+        input: Box::new(input),
+        output: Box::new(output),
+    };
+    match name {
+        "==" | "<" => fun(type_.clone(), fun(type_, Type::Bool)),
+        "negate" => fun(type_.clone(), type_),
+        "fromInt" => fun(Type::Int, type_),
+        "fromFloat" => fun(Type::Float, type_),
+        "mix" => fun(Type::Float, fun(type_.clone(), fun(type_.clone(), type_))),
+        _ => fun(type_.clone(), fun(type_.clone(), type_)),
+    }
+}
+
+/// The name of the n-th type variable of a type: `a` to `z`, then `a1` and so on.
+fn variable_name(n: usize) -> String {
+    let letter = char::from(b'a' + (n % 26) as u8);
+    match n / 26 {
+        0 => letter.to_string(),
+        round => format!("{letter}{round}"),
+    }
+}
+
+/// Every type error found in a program, reported together.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Could not type check the program, found {} error(s)", .errors.len())]
+#[diagnostic(code(easl::typeck::errors))]
+pub struct TypeErrors {
+    #[related]
+    pub errors: Vec<TypeError>,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum TypeError {
+    #[error("Expected {expected}, found {found}")]
+    #[diagnostic(code(easl::typeck::mismatch))]
+    Mismatch {
+        #[source_code]
+        source_code: NamedSource,
+        expected: String,
+        found: String,
+        #[label("Expected {expected} because of this")]
+        expected_at: Option<SourceSpan>,
+        #[label("This is {found}")]
+        found_at: SourceSpan,
+    },
+    #[error("Infinite type")]
+    #[diagnostic(
+        code(easl::typeck::infinite_type),
+        help = "This usually means a function is applied to itself"
+    )]
+    InfiniteType {
+        #[source_code]
+        source_code: NamedSource,
+        #[label("This expression would have a type containing itself")]
+        expression: SourceSpan,
+    },
+    #[error("No record has a field '{field}'")]
+    #[diagnostic(code(easl::typeck::unknown_field))]
+    UnknownField {
+        #[source_code]
+        source_code: NamedSource,
+        field: String,
+        #[label("Unknown field")]
+        this_field: SourceSpan,
+    },
+    #[error("{type_} has no field '{field}'")]
+    #[diagnostic(code(easl::typeck::no_such_field))]
+    NoSuchField {
+        #[source_code]
+        source_code: NamedSource,
+        field: String,
+        type_: String,
+        #[label("No such field")]
+        this_field: SourceSpan,
+    },
+    #[error("Can't tell which record the field '{field}' belongs to")]
+    #[diagnostic(
+        code(easl::typeck::ambiguous_field),
+        help = "Several records have a field of this name, use the record in a way that makes \
+                its type clear"
+    )]
+    AmbiguousField {
+        #[source_code]
+        source_code: NamedSource,
+        field: String,
+        #[label("This field")]
+        this_field: SourceSpan,
+    },
+}
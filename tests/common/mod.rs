@@ -0,0 +1,138 @@
+//! Loading, checking and running easl programs from tests.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use easl::interpreter::{InterpreterError, Limits};
+use easl::modules::{ModuleError, Program};
+use easl::parser::ParserWarning;
+use easl::source::SourceDb;
+use easl::typeck::TypeErrors;
+
+/// Files written to a directory of their own, which is removed again when this is dropped.
+pub struct Project {
+    pub dir: PathBuf,
+}
+
+impl Project {
+    pub fn new(files: &[(&str, &str)]) -> Self {
+        static PROJECTS: AtomicUsize = AtomicUsize::new(0);
+        let project = PROJECTS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir()
+            .join(format!("easl-tests-{}-{project}", std::process::id()));
+        for (path, text) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        Self { dir }
+    }
+
+    /// Loads the program in `entry`, with the project's directory as the search path.
+    pub fn load(&self, entry: &str, sources: &mut SourceDb) -> Result<Program, Failure> {
+        let search_path = [self.dir.clone()];
+        easl::modules::load(&self.dir.join(entry), &search_path, sources).map_err(Failure::Module)
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Why a program didn't run.
+#[derive(Debug)]
+pub enum Failure {
+    Module(ModuleError),
+    Types(TypeErrors),
+    Runtime(InterpreterError),
+}
+
+impl Failure {
+    /// The messages of the errors, without their source code.
+    pub fn messages(&self) -> Vec<String> {
+        match self {
+            Failure::Module(ModuleError::ParserErrors(errors)) => {
+                errors.errors.iter().map(ToString::to_string).collect()
+            }
+            Failure::Module(error) => vec![error.to_string()],
+            Failure::Types(errors) => errors.errors.iter().map(ToString::to_string).collect(),
+            Failure::Runtime(error) => vec![error.to_string()],
+        }
+    }
+
+    pub fn contains(&self, message: &str) -> bool {
+        self.messages().iter().any(|found| found.contains(message))
+    }
+}
+
+/// The outcome of checking a program that has no type errors.
+#[derive(Debug)]
+pub struct Checked {
+    /// `name :: type` for every top level definition.
+    pub types: Vec<String>,
+    pub warnings: Vec<ParserWarning>,
+}
+
+impl Checked {
+    /// The type of the top level definition `name`.
+    pub fn type_of(&self, name: &str) -> &str {
+        let prefix = format!("{name} :: ");
+        let found = self.types.iter().find_map(|line| line.strip_prefix(&prefix));
+        found.unwrap_or_else(|| panic!("no definition named {name} in {:#?}", self.types))
+    }
+}
+
+/// Type checks the program made of `files`, starting at the first one.
+pub fn check_files(files: &[(&str, &str)]) -> Result<Checked, Failure> {
+    check_files_with(files, false)
+}
+
+/// Like `check_files`, showing what aliases stand for in errors if `expand_aliases` is set.
+pub fn check_files_with(files: &[(&str, &str)], expand_aliases: bool) -> Result<Checked, Failure> {
+    let project = Project::new(files);
+    let mut sources = SourceDb::new();
+    let mut program = project.load(files[0].0, &mut sources)?;
+    let ident_map = &mut program.ident_map;
+    let typed = easl::typeck::infer(&program.statements, ident_map, &sources, expand_aliases)
+        .map_err(Failure::Types)?;
+    let types = typed
+        .definitions
+        .iter()
+        .map(|(ident, type_)| {
+            let name = program.ident_map.get(ident).cloned().unwrap_or_default();
+            format!("{name} :: {}", type_.display(&program.ident_map, false))
+        })
+        .collect();
+    Ok(Checked { types, warnings: program.warnings })
+}
+
+/// Type checks the single file program `source`.
+pub fn check(source: &str) -> Result<Checked, Failure> {
+    check_files(&[("main.easl", source)])
+}
+
+/// Type checks and runs the program made of `files`, starting at the first one.
+pub fn run_files(files: &[(&str, &str)], limits: Limits) -> Result<(), Failure> {
+    let project = Project::new(files);
+    let mut sources = SourceDb::new();
+    let mut program = project.load(files[0].0, &mut sources)?;
+    let typed = easl::typeck::infer(&program.statements, &mut program.ident_map, &sources, false)
+        .map_err(Failure::Types)?;
+    easl::interpreter::interpret(
+        program.statements,
+        &sources,
+        program.ident_map,
+        typed.expressions,
+        limits,
+    )
+    .map_err(Failure::Runtime)
+}
+
+/// Type checks and runs the single file program `source` with the default limits.
+pub fn run(source: &str) -> Result<(), Failure> {
+    run_files(&[("main.easl", source)], Limits::default())
+}
@@ -0,0 +1,91 @@
+use easl::source::{FileId, Source};
+
+fn format(text: &str) -> String {
+    easl::formatter::format(Source { file: FileId::default(), name: "main.easl", text }).unwrap()
+}
+
+#[test]
+fn negative_literal_patterns_keep_their_sign_attached() {
+    let source = "f (-1) = 0\nf n = case n of -2.5 -> 1; _ -> n\n";
+    assert_eq!(format(source), source);
+}
+
+#[test]
+fn spacing_is_normalized_and_comments_are_kept() {
+    let source = "-- Colors\nx   =  ( 1+2 ) *3 -- three\n\n\n\ny = [ 1 ,2 ]\n";
+    let formatted = "-- Colors\nx = (1 + 2) * 3 -- three\n\ny = [1, 2]\n";
+    assert_eq!(format(source), formatted);
+}
+
+#[test]
+fn ascriptions_are_moved_above_their_definitions() {
+    let source = "
+f x = x + 1
+g = 2
+f :: Int -> Int
+a <+> b = a
+(<+>) :: Int -> Int -> Int
+";
+    // Blank lines at the start are dropped as well.
+    let formatted = "\
+f :: Int -> Int
+f x = x + 1
+g = 2
+(<+>) :: Int -> Int -> Int
+a <+> b = a
+";
+    assert_eq!(format(source), formatted);
+}
+
+#[test]
+fn formatting_twice_changes_nothing_more() {
+    let source = "
+f :: Int ->Int
+f n|n<0 = 0
+   |otherwise=n
+  where m = n
+g = case 1 of
+    0 -> \"zero\\n\"
+    _ -> \"other\"
+h   = let a=1; b =2 in a+b -- sum
+";
+    let once = format(source);
+    assert_eq!(format(&once), once);
+}
+
+#[test]
+fn lines_that_dont_parse_are_left_as_they_are() {
+    let source = "x =  1\ny = = 2\n";
+    assert_eq!(format(source), "x = 1\ny = = 2\n");
+}
+
+#[test]
+fn check_reports_unformatted_files_without_changing_them() {
+    let dir = std::env::temp_dir().join(format!("easl-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.easl");
+    std::fs::write(&path, "x  = 1\n").unwrap();
+    let fmt = |check: bool| {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_easl"));
+        command.arg("fmt").arg(&path);
+        if check {
+            command.arg("--check");
+        }
+        command.output().unwrap().status.success()
+    };
+    assert!(!fmt(true));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "x  = 1\n");
+    assert!(fmt(false));
+    assert!(fmt(true));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "x = 1\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn blocks_keep_their_layout() {
+    let source = "area s = case s of\n    Circle r ->  r*r\n    Rect w h -> w*h\n  where\n    \
+                  k  =  1\n";
+    let formatted = "area s = case s of\n    Circle r -> r * r\n    Rect w h -> w * h\n  \
+                     where\n    k = 1\n";
+    assert_eq!(format(source), formatted);
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::{run_files, Failure, Project};
+use easl::interpreter::Limits;
+use easl::source::SourceDb;
+use miette::Diagnostic;
+
+#[test]
+fn modules_with_the_same_name_in_different_directories_are_kept_apart() {
+    let main = "
+include \"colors.easl\"
+import Shades
+x = Shades.dark + red
+check = case x of 3 -> ()
+";
+    let files = [
+        ("a/main.easl", main),
+        ("a/colors.easl", "red = 1\n"),
+        ("b/colors.easl", "red = 2\n"),
+        ("Shades.easl", "include \"b/colors.easl\"\ndark = red\n"),
+    ];
+    run_files(&files, Limits::default()).unwrap();
+}
+
+#[test]
+fn modules_with_the_same_name_in_one_file_are_reported() {
+    let files = [
+        ("main.easl", "include \"a/colors.easl\"\ninclude \"b/colors.easl\"\nx = red\n"),
+        ("a/colors.easl", "red = 1\n"),
+        ("b/colors.easl", "red = 2\n"),
+    ];
+    let error = run_files(&files, Limits::default()).unwrap_err();
+    assert!(error.contains("Two different modules are named 'colors'"), "{error:?}");
+}
+
+#[test]
+fn selective_imports_hide_the_other_names_unless_qualified() {
+    let palettes = "sunset = 1\nocean = 2\n";
+    let main = "import Palettes (sunset)\nx = sunset + Palettes.ocean\n";
+    let files = [("main.easl", main), ("Palettes.easl", palettes)];
+    run_files(&files, Limits::default()).unwrap();
+
+    let main = "import Palettes (sunset)\nx = ocean\n";
+    let files = [("main.easl", main), ("Palettes.easl", palettes)];
+    let error = run_files(&files, Limits::default()).unwrap_err();
+    assert!(error.contains("Unknown identifier 'ocean'"), "{error:?}");
+
+    let main = "import Palettes (sunset, nope)\n";
+    let files = [("main.easl", main), ("Palettes.easl", palettes)];
+    let error = run_files(&files, Limits::default()).unwrap_err();
+    assert!(error.contains("'nope' is not defined in module 'Palettes'"), "{error:?}");
+}
+
+#[test]
+fn plain_imports_make_every_name_visible() {
+    let main = "import Palettes\ncheck = case (sunset, Palettes.ocean) of (1, 2) -> ()\n";
+    let files = [("main.easl", main), ("Palettes.easl", "sunset = 1\nocean = 2\n")];
+    run_files(&files, Limits::default()).unwrap();
+}
+
+#[test]
+fn include_cycles_are_reported() {
+    let files = [
+        ("main.easl", "include \"a.easl\"\n"),
+        ("a.easl", "include \"b.easl\"\n"),
+        ("b.easl", "include \"a.easl\"\n"),
+    ];
+    let error = run_files(&files, Limits::default()).unwrap_err();
+    assert!(error.contains("Include cycle"), "{error:?}");
+}
+
+#[test]
+fn files_are_searched_next_to_the_including_file_first() {
+    let files = [
+        ("main.easl", "include \"lib/util.easl\"\ncheck = case double 2 of 8 -> ()\n"),
+        ("lib/util.easl", "include \"helper.easl\"\ndouble x = x * helper\n"),
+        ("lib/helper.easl", "helper = 4\n"),
+        ("helper.easl", "helper = 2\n"),
+    ];
+    run_files(&files, Limits::default()).unwrap();
+}
+
+#[test]
+fn nothing_a_module_imports_is_passed_on() {
+    let base = "
+record Point = { x :: Int }
+infixl 7 <+>
+a <+> b = a + b
+origin = Point { x = 0 }
+";
+    let middle = "import Base\nshifted = Point { x = 1 }\n";
+    let uses = [
+        ("x = origin", "Unknown identifier 'origin'"),
+        ("x :: Point\nx = shifted", "Unknown type 'Point'"),
+        ("x = 1 <+> 2", "Unknown identifier '<+>'"),
+    ];
+    for (main, message) in uses {
+        let main = format!("import Middle\n{main}\n");
+        let files = [("main.easl", main.as_str()), ("Middle.easl", middle), ("Base.easl", base)];
+        let error = run_files(&files, Limits::default()).unwrap_err();
+        assert!(error.contains(message), "{main}: {error:?}");
+    }
+}
+
+/// The name of the file the first label of `diagnostic` points into.
+fn file_name(diagnostic: &dyn Diagnostic) -> String {
+    let source = diagnostic.source_code().unwrap();
+    let span = diagnostic.labels().unwrap().next().unwrap();
+    let contents = source.read_span(span.inner(), 0, 0).unwrap();
+    contents.name().unwrap().to_string()
+}
+
+#[test]
+fn errors_in_included_files_point_into_them_and_back_to_the_includes() {
+    let files = [
+        ("main.easl", "include \"lib/colors.easl\"\nx = boom 1\n"),
+        ("lib/colors.easl", "include \"numbers.easl\"\nboom n = explode n\n"),
+        ("lib/numbers.easl", "explode n = [1] !! n\n"),
+    ];
+    let project = Project::new(&files);
+    let mut sources = SourceDb::new();
+    let mut program = project.load("main.easl", &mut sources).unwrap();
+    let typed = easl::typeck::infer(&program.statements, &mut program.ident_map, &sources, false)
+        .unwrap();
+    let error = easl::interpreter::interpret(
+        program.statements,
+        &sources,
+        program.ident_map,
+        typed.expressions,
+        Limits::default(),
+    )
+    .unwrap_err();
+    assert!(file_name(&error).ends_with("numbers.easl"), "{error:?}");
+
+    let report = sources.report(error);
+    let includers: Vec<_> = report
+        .related()
+        .unwrap()
+        .filter(|related| related.to_string() == "Included from here")
+        .map(file_name)
+        .collect();
+    assert_eq!(includers.len(), 2, "{includers:?}");
+    assert!(includers[0].ends_with("colors.easl") && includers[1].ends_with("main.easl"));
+}
+
+#[test]
+fn type_errors_in_included_files_point_into_them() {
+    let files = [("main.easl", "include \"lib.easl\"\nx = 1\n"), ("lib.easl", "y = 1 + True\n")];
+    let error = common::check_files(&files).unwrap_err();
+    let Failure::Types(errors) = error else { panic!("{error:?}") };
+    assert!(file_name(&errors.errors[0]).ends_with("lib.easl"), "{errors:?}");
+}
@@ -0,0 +1,189 @@
+use easl::parser::ast::Statement;
+use easl::parser::ParserError;
+
+#[test]
+fn every_syntax_error_is_reported_and_the_rest_is_parsed() {
+    let source = "
+a = 1 +
+b = 2
+c = (3
+";
+    let (statements, ident_map, errors) = easl::parser::parse_recovering(source);
+    assert_eq!(errors.len(), 2, "{errors:?}");
+    let b = ident_map.get_from_name("b").unwrap();
+    let parsed = |statement: &Statement| {
+        matches!(statement, Statement::Assignment { ident, .. } if *ident == b)
+    };
+    assert!(statements.iter().any(parsed));
+}
+
+#[test]
+fn hex_colors_need_three_six_or_eight_hex_digits() {
+    for source in ["x = #ff88", "x = #gg0000", "x = #ff8800f"] {
+        let (_, _, errors) = easl::parser::parse_recovering(source);
+        assert_eq!(errors.len(), 1, "{source}: {errors:?}");
+    }
+}
+
+#[test]
+fn more_parameters_than_the_ascription_allows_are_reported_on_the_parameters() {
+    let source = "f :: Int -> Int\nf a b = a\n";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let [ParserError::ArityMismatch { expected, found, params, .. }] = errors.as_slice() else {
+        panic!("{errors:?}");
+    };
+    assert_eq!((*expected, *found), (1, 2));
+    assert_eq!(&source[params.offset()..params.offset() + params.len()], "a b");
+}
+
+#[test]
+fn where_clauses_are_bound_once_for_all_of_their_guards() {
+    use easl::parser::ast::{Expression, Primary};
+
+    let source = "
+sign n | n < zero = negative | n == zero = zero | otherwise = positive
+    where zero = 0
+          negative = 0 - 1
+          positive = 1
+";
+    let (statements, _, errors) = easl::parser::parse_recovering(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    let Statement::Assignment { expr, .. } = &statements[0] else {
+        panic!("{statements:?}");
+    };
+    let Expression::Primary(lambda) = &expr.inner else { panic!("{expr:?}") };
+    let Primary::Lambda { body, .. } = &lambda.inner else { panic!("{lambda:?}") };
+    let Expression::Case { arms, .. } = &body.inner else { panic!("{body:?}") };
+    let [arm] = arms.as_slice() else { panic!("{arms:?}") };
+    assert_eq!((arm.bindings.len(), arm.alternatives.len()), (3, 3));
+}
+
+/// The string that `x` is defined as in `source`.
+fn string_value(source: &str) -> String {
+    use easl::parser::ast::{Expression, Primary};
+
+    let (statements, _, errors) = easl::parser::parse_recovering(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    match &statements[0] {
+        Statement::Assignment { expr, .. } => match &expr.inner {
+            Expression::Primary(primary) => match &primary.inner {
+                Primary::String(string) => string.clone(),
+                other => panic!("{other:?}"),
+            },
+            other => panic!("{other:?}"),
+        },
+        other => panic!("{other:?}"),
+    }
+}
+
+#[test]
+fn strings_are_stored_unquoted_and_unescaped() {
+    assert_eq!(string_value(r#"x = "plain""#), "plain");
+    assert_eq!(string_value(r#"x = "a\n\t\"b\"\\\u{1F308}""#), "a\n\t\"b\"\\\u{1F308}");
+    assert_eq!(string_value(r#"x = """#), "");
+}
+
+#[test]
+fn unterminated_strings_are_reported_up_to_the_end_of_the_line() {
+    let source = "x = \"never closed\ny = 1\n";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let [ParserError::UnterminatedString { string, .. }] = errors.as_slice() else {
+        panic!("{errors:?}");
+    };
+    assert_eq!(&source[string.offset()..string.offset() + string.len()], "\"never closed");
+}
+
+#[test]
+fn invalid_escapes_are_reported_on_the_escape() {
+    for (source, escape) in [(r#"x = "a\qb""#, r"\q"), (r#"x = "\u{110000}""#, r"\u{110000}")] {
+        let (_, _, errors) = easl::parser::parse_recovering(source);
+        let [ParserError::InvalidEscape { escape: span, .. }] = errors.as_slice() else {
+            panic!("{source}: {errors:?}");
+        };
+        assert_eq!(&source[span.offset()..span.offset() + span.len()], escape);
+    }
+}
+
+#[test]
+fn non_associative_operators_cant_be_chained() {
+    let source = "infix 4 <=>\na <=> b = a == b\nx = 1 <=> 2 <=> 3\n";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let [ParserError::AmbiguousOperators { first, second, .. }] = errors.as_slice() else {
+        panic!("{errors:?}");
+    };
+    let chained = (source.find("<=> 2").unwrap(), source.rfind("<=>").unwrap());
+    assert_eq!((first.offset(), second.offset()), chained);
+}
+
+#[test]
+fn each_partial_application_spans_its_function_and_arguments() {
+    use easl::parser::ast::Expression;
+
+    let source = "f a b c = a\nx = f 1 (2 + 3) 4\n";
+    let (statements, ident_map, errors) = easl::parser::parse_recovering(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    let x = ident_map.get_from_name("x").unwrap();
+    let Some(Statement::Assignment { expr, .. }) = statements.iter().find(|statement| {
+        matches!(statement, Statement::Assignment { ident, .. } if *ident == x)
+    }) else {
+        panic!("{statements:?}");
+    };
+    let mut spans = Vec::new();
+    let mut application = expr;
+    while let Expression::FunctionApplication { function, argument } = &application.inner {
+        spans.push((&source[application.span.clone()], &source[argument.span.clone()]));
+        application = function;
+    }
+    assert_eq!(spans, [("f 1 (2 + 3) 4", "4"), ("f 1 (2 + 3)", "2 + 3"), ("f 1", "1")]);
+}
+
+#[test]
+fn constructor_patterns_need_a_known_constructor_and_all_its_arguments() {
+    let source = "
+data Mode = Solid Color | Gradient Color Color
+f (Gradient a) = a
+g (Dotted a) = a
+";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let [arity, ParserError::UnknownConstructor { ident, .. }] = errors.as_slice() else {
+        panic!("{errors:?}");
+    };
+    let ParserError::ConstructorArityMismatch { expected, found, .. } = arity else {
+        panic!("{arity:?}");
+    };
+    assert_eq!((*expected, *found, ident.as_str()), (2, 1, "Dotted"));
+}
+
+#[test]
+fn lines_of_a_block_have_to_be_aligned() {
+    let source = "
+f n = case n of
+    0 -> 1
+      1 -> 2
+x = let a = 1
+      b = 2
+    in a + b
+";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let text = |span: &miette::SourceSpan| &source[span.offset()..span.offset() + span.len()];
+    let misaligned: Vec<_> = errors
+        .iter()
+        .map(|error| match error {
+            ParserError::MisalignedLine { keyword, found, expected, line, block, .. } => {
+                (*keyword, *found, *expected, text(line), text(block))
+            }
+            other => panic!("{other:?}"),
+        })
+        .collect();
+    assert_eq!(misaligned, [("of", 7, 5, "1", "0"), ("let", 7, 9, "b", "a")]);
+}
+
+#[test]
+fn indented_statements_are_reported() {
+    let source = "a = 1\n  b = 2\n";
+    let (_, _, errors) = easl::parser::parse_recovering(source);
+    let [ParserError::UnexpectedIndentation { line, .. }] = errors.as_slice() else {
+        panic!("{errors:?}");
+    };
+    assert_eq!(&source[line.offset()..line.offset() + line.len()], "b");
+}
@@ -0,0 +1,472 @@
+mod common;
+
+use common::{check, run, run_files, Failure};
+use easl::interpreter::{InterpreterError, Limits};
+use easl::parser::ParserWarning;
+
+#[test]
+fn depth_limit_is_reported_before_the_stack_overflows() {
+    let program = "
+down :: Int -> Int
+down 0 = 0
+down n | n > 0 = 1 + (let m = n - 1 in down m) | otherwise = 0
+x = down 100000
+";
+    let limits = Limits { fuel: 100_000_000, depth: Limits::MAX_DEPTH };
+    let error = run_files(&[("main.easl", program)], limits).unwrap_err();
+    assert!(
+        matches!(error, Failure::Runtime(InterpreterError::RecursionLimit { .. })),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn generic_numbers_become_colors_where_they_are_used_as_colors() {
+    let program = "
+one u = 1
+c :: Color
+c = one 0
+d = alpha 0.5 c
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn generic_numbers_become_values_of_num_instances_where_they_are_used_as_them() {
+    let program = "
+record V = { x :: Int, y :: Int }
+instance Add V where
+    a + b = V { x = a.x + b.x, y = a.y + b.y }
+instance Num V where
+    a - b = V { x = a.x - b.x, y = a.y - b.y }
+    a * b = V { x = a.x * b.x, y = a.y * b.y }
+    a / b = V { x = a.x / b.x, y = a.y / b.y }
+    negate a = V { x = 0 - a.x, y = 0 - a.y }
+    fromInt n = V { x = n, y = n }
+one u = 1
+v :: V
+v = one 0
+w = v.x
+check = case w of 1 -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn numbers_as_colors_keep_the_alpha() {
+    let program = "
+c = alpha 0.5 (1 :: Color)
+check = case 2 * c == alpha 0.5 (2 :: Color) of True -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn hex_colors_of_every_length_are_the_same_colors() {
+    let program = "
+check = case (#f80 == #FF8800, #ff8800ff == #ff8800, #000 == rgb 0 0 0) of
+    (True, True, True) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn colors_are_shown_as_hex_in_errors() {
+    let error = run("x = case (#ff880080, #f80) of (_, #000) -> ()").unwrap_err();
+    let Failure::Runtime(InterpreterError::NonExhaustiveCase { value, .. }) = error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(value, "(#FF880080, #FF8800)");
+}
+
+#[test]
+fn functions_with_several_parameters_can_be_applied_partially() {
+    let program = "
+add3 :: Int -> Int -> Int -> Int
+add3 a b c = a * 100 + b * 10 + c
+add12 = add3 1 2
+check = case (add3 1 2 3, add12 4) of (123, 124) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn let_bindings_shadow_and_see_the_ones_before_them() {
+    let program = "
+x = 1
+y = let x = 2; z = x + 1 in z * x
+check = case (x, y) of (1, 6) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn let_bindings_are_not_visible_outside_of_the_let() {
+    let error = run("y = let z = 1 in z\nw = z\n").unwrap_err();
+    assert!(error.contains("Unknown identifier 'z'"), "{error:?}");
+}
+
+#[test]
+fn where_clauses_see_the_parameters_of_their_own_clause() {
+    let program = "
+sign n | n < zero = negative | n == zero = zero | otherwise = positive
+    where zero = 1
+          negative = zero - 1
+          positive = zero + 1
+f 0 = z where z = 10
+f n = n + z where z = 1
+check = case (sign (0 - 5), sign 1, sign 7, f 0, f 5) of (0, 1, 2, 10, 6) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn guards_are_tried_in_order_and_fall_through_to_the_next_arm() {
+    let program = "
+classify n = case n of
+    0 -> 0
+    m | m < 10 -> 1 | m < 100 -> 2
+    _ -> 3
+check = case (classify 0, classify 5, classify 50, classify 500) of (0, 1, 2, 3) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn negative_numbers_can_be_matched() {
+    let program = "
+f (-1) = 0
+f n = n
+g x = case x of -0.5 -> True; _ -> False
+check = case (f (0 - 1), f 3, g (0.0 - 0.5), g 0.5) of (0, 3, True, False) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn non_exhaustive_cases_point_at_the_scrutinee() {
+    let program = "x = case 1 + 2 of 0 -> ()\n";
+    let error = run(program).unwrap_err();
+    let Failure::Runtime(InterpreterError::NonExhaustiveCase { value, this_scrutinee, .. }) =
+        error
+    else {
+        panic!("{error:?}");
+    };
+    assert_eq!(value, "3");
+    let scrutinee = this_scrutinee.offset()..this_scrutinee.offset() + this_scrutinee.len();
+    assert_eq!(&program[scrutinee], "1 + 2");
+}
+
+#[test]
+fn arrays_can_be_built_indexed_mapped_and_folded() {
+    let program = "
+xs = [1..4]
+check = case (length xs, xs !! 0, map (\\x -> x * 2) xs, fold (\\a x -> a + x) 0 xs) of
+    (4, 1, [2, 4, 6, 8], 10) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn array_patterns_match_arrays_of_their_length() {
+    let program = "
+describe [] = 0
+describe [x] = x
+describe [x, y] = x + y
+describe _ = 0 - 1
+check = case (describe [], describe [5], describe [1, 2], describe [1..3]) of
+    (0, 5, 3, -1) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn array_patterns_have_a_single_element_type() {
+    let error = run("f [a, True] = a + 1\n").unwrap_err();
+    assert!(matches!(error, Failure::Types(_)), "{error:?}");
+}
+
+#[test]
+fn indices_out_of_bounds_are_reported_with_the_length() {
+    let error = run("x = [1, 2, 3] !! 3\n").unwrap_err();
+    let Failure::Runtime(InterpreterError::IndexOutOfBounds { index, length, .. }) = error else {
+        panic!("{error:?}");
+    };
+    assert_eq!((index.as_str(), length), ("3", 3));
+}
+
+#[test]
+fn ranges_longer_than_the_fuel_are_reported_instead_of_built() {
+    let error = run("x = length [0..100000000000]\n").unwrap_err();
+    assert!(
+        matches!(error, Failure::Runtime(InterpreterError::RangeTooLong { .. })),
+        "{error:?}"
+    );
+    let limits = Limits { fuel: 10, depth: 10 };
+    run_files(&[("main.easl", "x = length [1..10]\n")], limits).unwrap();
+}
+
+#[test]
+fn tuples_and_records_can_be_built_and_taken_apart() {
+    let program = "
+record Point = { x :: Int, y :: Int }
+swap (a, b) = (b, a)
+p = Point { x = 1, y = 2 }
+sum = let (a, b) = swap (p.x, p.y) in a * 10 + b
+check = case (sum, p.y) of (21, 2) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn operators_of_the_same_precedence_associate_to_the_left() {
+    let program = "
+check = case (10 - 3 - 2, 1 + 2 + 3, 2 + 3 * 4, 100 / 10 / 5, 7 % 4 * 2) of
+    (5, 6, 14, 2, 6) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn declared_fixities_decide_how_operators_group() {
+    let program = "
+infixr 5 <+>
+a <+> b = a * 10 + b
+infixl 5 <->
+a <-> b = a * 10 + b
+infixl 7 <*>
+a <*> b = a * b
+check = case (1 <+> 2 <+> 3, 1 <-> 2 <-> 3, 1 + 2 <*> 3) of (33, 123, 7) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn builtins_take_their_arguments_one_after_another() {
+    let program = "
+red = rgb 1 0 0
+check = case (red == #f00, mix 0.5 0 10, fold (\\a x -> a * x) 1 [1..5]) of
+    (True, 5, 120) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn data_types_are_built_with_constructors_and_taken_apart_with_patterns() {
+    let program = "
+data Mode = Solid Color | Gradient Color Color | Off
+data Tree = Leaf | Node Tree Int Tree
+brightness mode = case mode of
+    Solid c -> 1
+    Gradient a b -> 2
+    Off -> 0
+sum Leaf = 0
+sum (Node l n r) = sum l + n + sum r
+tree = Node (Node Leaf 1 Leaf) 2 (Node Leaf 3 Leaf)
+modes = map brightness [Solid #f00, Gradient #000 #fff, Off]
+check = case (modes, sum tree) of ([1, 2, 0], 6) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn constructors_are_shown_with_their_arguments_in_errors() {
+    let program = "
+data Mode = Solid Color | Gradient Color Color | Nested Mode
+x = case Nested (Gradient #f00 #00f) of Nested (Solid c) -> ()
+";
+    let error = run(program).unwrap_err();
+    let Failure::Runtime(InterpreterError::NonExhaustiveCase { value, .. }) = error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(value, "Nested (Gradient #FF0000 #0000FF)");
+}
+
+#[test]
+fn generic_functions_work_on_every_type_they_are_used_at() {
+    let program = "
+twice f x = f (f x)
+pair x = (x, x)
+check = case (twice (\\n -> n * 3) 2, twice (\\b -> !b) True, pair 1, pair False) of
+    (18, True, (1, 1), (False, False)) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn colors_are_added_and_scaled_without_leaving_the_alpha_range() {
+    let program = "
+c = #ff000080
+half = alpha (128 / 255) (0.5 * #f00)
+check = case (c + #00ff0080, 0.5 * c == half, c - c, -c, c == #ff000080, c == #ff0000) of
+    (#ffff00ff, True, #00000000, negated, True, False) | negated + c == #000000 -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn ints_wrap_and_divide_without_a_fraction() {
+    let program = "
+max = 9223372036854775807
+check = case (max + 1 == 0 - max - 1, 7 / 2, 0 - 7 / 2, (0 - 7) % 3, 0xffffffffffffffff) of
+    (True, 3, -3, 2, -1) -> ()
+bits = case (0b1100 .&. 0b1010, 0b1100 .|. 0b1010, 0b1100 .^. 0b1010, 1 .<<. 4, 0 - 16 .>>. 2) of
+    (8, 14, 6, 16, -4) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn ints_and_floats_are_converted_explicitly() {
+    let program = "
+n = 7
+check = case (toFloat n / 2.0, floor 2.7, floor (0.0 - 2.5), round 2.5, round (0.0 - 2.5)) of
+    (3.5, 2, -3, 3, -3) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn dividing_ints_by_zero_is_reported() {
+    for program in ["x = 1 / (1 - 1)\n", "x = 1 % 0\n"] {
+        let error = run(program).unwrap_err();
+        assert!(
+            matches!(error, Failure::Runtime(InterpreterError::DivisionByZero { .. })),
+            "{program}: {error:?}"
+        );
+    }
+}
+
+#[test]
+fn lambdas_take_several_parameters_and_patterns() {
+    let program = "
+data Size = Size Int Int
+add3 = \\a b c -> a + b + c
+first = \\(x, _) -> x
+width = \\(Size w _) -> w
+always = \\x _ -> x
+check = case (add3 1 2 3, first (4, True), width (Size 5 6), always 7 \"ignored\") of
+    (6, 4, 5, 7) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn wildcards_bind_nothing() {
+    run("both = \\_ _ -> 1\nx = both 2 3\n").unwrap();
+    let error = run("f = \\_ -> _\n").unwrap_err();
+    assert_eq!(error.messages(), ["'_' can only be used in patterns"]);
+}
+
+#[test]
+fn lambda_parameters_that_dont_match_are_reported() {
+    let error = run("data Light = On | Off\nf = \\On -> 1\nx = f Off\n").unwrap_err();
+    let Failure::Runtime(InterpreterError::ParamPatternMismatch { value, .. }) = error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(value, "Off");
+}
+
+#[test]
+fn stack_that_cant_be_reserved_is_reported() {
+    let limits = Limits { fuel: 1_000, depth: Limits::MAX_DEPTH * 1_000_000_000 };
+    let error = run_files(&[("main.easl", "x = 1\n")], limits).unwrap_err();
+    assert!(matches!(error, Failure::Runtime(InterpreterError::NoStack { .. })), "{error:?}");
+}
+
+#[test]
+fn local_definitions_can_only_use_the_ones_before_them() {
+    let program = "
+f n = let g m = if m > 0 then g (m - 1) else 0 in g n
+h n = k n
+    where
+        k m = l m
+        l m = m
+";
+    let error = run(program).unwrap_err();
+    assert_eq!(
+        error.messages(),
+        ["'g' is used before it's defined", "'l' is used before it's defined"]
+    );
+}
+
+#[test]
+fn annotated_numbers_are_values_of_the_annotated_type() {
+    let program = "
+check = case ((negate 2 :: Float), mix 0.5 0 (10 :: Float), (1 :: Float) / 4) of
+    (-2.0, 5.0, 0.25) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn blocks_and_continuations_are_laid_out_by_indentation() {
+    let program = "
+data Shape = Circle Int | Rect Int Int
+area shape = case shape of
+    Circle r -> three * r * r
+    Rect w h -> w * h
+  where
+    three = 3
+total = area (Circle 2)
+    + area (Rect 2 3)
+tabbed n = case n of
+\t0 -> 1
+\t_ -> n
+check = let
+        a = total
+        b = tabbed 0
+    in case a + b of
+        19 -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn functions_are_defined_by_guarded_equations() {
+    let program = "
+frag :: Int -> Int
+frag 0 = 100
+frag pos | pos < 10 = 1 | pos < 30 = 2
+frag 50 = 200
+frag pos | otherwise = 3
+check = case (frag 0, frag 5, frag 20, frag 50, frag 40) of (100, 1, 2, 200, 3) -> ()
+";
+    run(program).unwrap();
+}
+
+#[test]
+fn clauses_of_a_function_have_to_be_together() {
+    let error = run("f 0 = 1\ng = 2\nf n = n\n").unwrap_err();
+    assert_eq!(error.messages(), ["Identifier defined multiple times"]);
+}
+
+#[test]
+fn clauses_and_guards_after_ones_that_match_everything_are_unreachable() {
+    let program = "
+f n = 0
+f 1 = 1
+g n | otherwise = 0 | n < 0 = 1
+h (0, y) = y
+h (x, _) = x
+h (1, 2) = 3
+k 0 = 1
+k n | n > 0 = n
+k n = 0
+";
+    let checked = check(program).unwrap();
+    let text = |span: &miette::SourceSpan| &program[span.offset()..span.offset() + span.len()];
+    let unreachable: Vec<_> = checked
+        .warnings
+        .iter()
+        .map(|warning| match warning {
+            ParserWarning::UnreachableClause { unreachable, covering, .. } => {
+                (text(unreachable), text(covering))
+            }
+        })
+        .collect();
+    assert_eq!(
+        unreachable,
+        [("f 1", "f n"), ("| n < 0 = 1", "| otherwise = 0"), ("h (1, 2)", "h (x, _)")]
+    );
+}
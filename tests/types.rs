@@ -0,0 +1,287 @@
+mod common;
+
+use common::{check, check_files_with, Project};
+use easl::typeck::TypeError;
+
+#[test]
+fn aliases_are_shown_in_errors_at_calls() {
+    let program = "
+type Position = Int
+f :: Position -> Int
+f p = p
+z = f True
+";
+    let error = check(program).err().unwrap();
+    assert!(error.contains("Expected Position, found Bool"), "{error:?}");
+}
+
+#[test]
+fn tuples_and_records_have_their_own_types() {
+    let program = "
+record Point = { x :: Int, y :: Float }
+p = Point { x = 1, y = 2.0 }
+pair = (p.x, p.y, True)
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("p"), "Point");
+    assert_eq!(checked.type_of("pair"), "(Int, Float, Bool)");
+}
+
+#[test]
+fn fields_have_to_belong_to_the_record() {
+    let program = "
+record Point = { x :: Int, y :: Int }
+record Size = { width :: Int }
+s = Size { width = 1 }
+a = s.x
+b = s.depth
+";
+    let error = check(program).unwrap_err();
+    // Only `Point` has an `x`, so `s` is expected to be one.
+    assert!(error.contains("Expected Point, found Size"), "{error:?}");
+    assert!(error.contains("No record has a field 'depth'"), "{error:?}");
+}
+
+#[test]
+fn tuples_of_different_lengths_are_different_types() {
+    let program = "f :: (Int, Int) -> Int\nf (a, b) = a\nx = f (1, 2, 3)\n";
+    let error = check(program).unwrap_err();
+    let message = "Expected (Int, Int), found (Num a, Num b, Num c) => (a, b, c)";
+    assert!(error.contains(message), "{error:?}");
+}
+
+#[test]
+fn constructors_are_functions_to_their_data_type() {
+    let program = "
+data Mode = Solid Color | Gradient Color Color | Off
+a = Gradient
+b = Off
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("a"), "Color -> Color -> Mode");
+    assert_eq!(checked.type_of("b"), "Mode");
+    let error = check("data Mode = Solid Color\nx = Solid True\n").unwrap_err();
+    assert!(error.contains("Expected Color, found Bool"), "{error:?}");
+}
+
+#[test]
+fn aliases_are_expanded_in_errors_on_request() {
+    let program = "
+type Position = Int
+type Span = (Position, Position)
+f :: Span -> Int
+f (a, b) = a
+z = f True
+";
+    let error = check_files_with(&[("main.easl", program)], true).unwrap_err();
+    assert!(error.contains("Expected (Int, Int), found Bool"), "{error:?}");
+}
+
+#[test]
+fn aliases_are_interchangeable_with_what_they_stand_for() {
+    let program = "
+type Position = Int
+f :: Position -> Int
+f p = p + 1
+g :: Int -> Position
+g n = f n
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("g"), "Int -> Position");
+}
+
+#[test]
+fn newtypes_are_distinct_from_what_they_wrap() {
+    let program = "
+newtype Seconds = Seconds Int
+later :: Seconds -> Seconds
+later (Seconds s) = Seconds (s + 1)
+";
+    check(program).unwrap();
+    let uses = [
+        ("x = later 1", "Expected Seconds, found a number"),
+        ("x :: Int\nx = later (Seconds 1)", "Expected Int, found Seconds"),
+    ];
+    for (use_, message) in uses {
+        let error = check(&format!("{program}{use_}\n")).unwrap_err();
+        assert!(error.contains(message), "{use_}: {error:?}");
+    }
+}
+
+#[test]
+fn desugared_expressions_with_the_same_span_have_their_own_types() {
+    use easl::parser::ast::{Expression, Primary, Statement};
+    use easl::source::SourceDb;
+
+    let source = "h = (+) 1.5 2\n";
+    let (statements, mut ident_map, errors) = easl::parser::parse_recovering(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    let mut sources = SourceDb::new();
+    sources.add("main.easl".to_string(), source.to_string(), None);
+    let typed = easl::typeck::infer(&statements, &mut ident_map, &sources, false).unwrap();
+
+    // `(+)` is `\lhs -> \rhs -> lhs + rhs`, every part of which spans the `(+)`.
+    let Statement::Assignment { expr, .. } = &statements[0] else { panic!("{statements:?}") };
+    let Expression::FunctionApplication { function, .. } = &expr.inner else { panic!("{expr:?}") };
+    let Expression::FunctionApplication { function: section, .. } = &function.inner else {
+        panic!("{function:?}")
+    };
+    let mut nodes = vec![section.as_ref()];
+    while let Expression::Primary(primary) = &nodes[nodes.len() - 1].inner {
+        let Primary::Lambda { body, .. } = &primary.inner else { break };
+        nodes.push(body);
+    }
+    let types: Vec<_> = nodes
+        .iter()
+        .map(|node| typed.expressions[&node.id].display(&ident_map, false).to_string())
+        .collect();
+    assert_eq!(types, ["Float -> Float -> Float", "Float -> Float", "Float"]);
+}
+
+#[test]
+fn definitions_without_signatures_get_the_most_general_types() {
+    let program = "
+identity x = x
+compose f g x = f (g x)
+n = 1
+m = 1.5
+twice f = compose f f
+shade c = alpha 0.5 c
+pair = (identity 1, identity True)
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("identity"), "a -> a");
+    assert_eq!(checked.type_of("compose"), "(a -> b) -> (c -> a) -> c -> b");
+    assert_eq!(checked.type_of("n"), "Int");
+    assert_eq!(checked.type_of("m"), "Float");
+    assert_eq!(checked.type_of("twice"), "(a -> a) -> a -> a");
+    assert_eq!(checked.type_of("shade"), "Color -> Color");
+    assert_eq!(checked.type_of("pair"), "(Int, Bool)");
+}
+
+#[test]
+fn the_types_command_lists_every_definition() {
+    let project = Project::new(&[("main.easl", "a <+> b = a\nsquare x = x * x\nn = 2\n")]);
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_easl"))
+        .arg("types")
+        .arg(project.dir.join("main.easl"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(listing, "(<+>) :: a -> b -> a\nsquare :: Num a => a -> a\nn :: Int\n");
+}
+
+/// The expected and found types of the type errors of `program`, with the source they point
+/// at.
+fn mismatches(program: &str) -> Vec<(String, Option<&str>, String, &str)> {
+    let error = check(program).unwrap_err();
+    let common::Failure::Types(errors) = error else { panic!("{error:?}") };
+    let text = |span: &miette::SourceSpan| &program[span.offset()..span.offset() + span.len()];
+    errors
+        .errors
+        .iter()
+        .map(|error| match error {
+            TypeError::Mismatch { expected, expected_at, found, found_at, .. } => {
+                (expected.clone(), expected_at.as_ref().map(text), found.clone(), text(found_at))
+            }
+            other => panic!("{other:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn mismatches_point_at_what_was_expected_and_what_was_found() {
+    let found = mismatches("a = rgb \"red\" 0 0\nb = 1 + True\nc :: Int\nc = 1.5\n");
+    assert_eq!(
+        found,
+        [
+            ("Float".to_string(), Some("rgb"), "String".to_string(), "\"red\""),
+            ("a number".to_string(), Some("1"), "Bool".to_string(), "True"),
+            ("Int".to_string(), Some(":: Int"), "a fractional number".to_string(), "1.5"),
+        ]
+    );
+}
+
+#[test]
+fn programs_with_type_errors_are_not_run() {
+    let error = common::run("x = [1] !! 5\ny = 1 + True\n").unwrap_err();
+    assert!(matches!(error, common::Failure::Types(_)), "{error:?}");
+}
+
+#[test]
+fn generic_functions_can_be_used_at_different_types() {
+    let program = "
+mirror :: (a, b) -> (b, a)
+mirror (x, y) = (y, x)
+compose :: (b -> c) -> (a -> b) -> a -> c
+compose f g x = f (g x)
+a = mirror (1, True)
+b = mirror (#fff, \"white\")
+c = compose (\\x -> x == 2) (\\s -> length s) [1, 2]
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("a"), "(Bool, Int)");
+    assert_eq!(checked.type_of("b"), "(String, Color)");
+    assert_eq!(checked.type_of("c"), "Bool");
+}
+
+#[test]
+fn type_variables_in_signatures_cant_be_narrowed_by_the_definition() {
+    let error = check("f :: a -> a\nf x = True\n").unwrap_err();
+    assert!(error.contains("Expected a, found Bool"), "{error:?}");
+}
+
+#[test]
+fn ints_and_floats_dont_mix() {
+    let found = mismatches("n :: Int\nn = 2\nx = n + 1.5\ny = n / 2.0\nz = floor n\n");
+    assert_eq!(
+        found,
+        [
+            ("Int".to_string(), Some("n"), "a fractional number".to_string(), "1.5"),
+            ("Int".to_string(), Some("n"), "a fractional number".to_string(), "2.0"),
+            ("Float".to_string(), Some("floor"), "Int".to_string(), "n"),
+        ]
+    );
+}
+
+#[test]
+fn lambda_parameters_are_typed_by_their_patterns() {
+    let program = "
+data Size = Size Int Int
+first = \\(x, _) -> x
+width = \\(Size w _) -> w
+always = \\x _ -> x
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("first"), "(a, b) -> a");
+    assert_eq!(checked.type_of("width"), "Size -> Int");
+    assert_eq!(checked.type_of("always"), "a -> b -> a");
+}
+
+#[test]
+fn annotations_inside_expressions_pick_the_type() {
+    let program = "
+a = (negate 2 :: Float)
+b = mix 0.5 0 (1 :: Color)
+c = let x = (3 :: Float) in x
+d = map (\\n -> (n :: Int)) [1]
+";
+    let checked = check(program).unwrap();
+    assert_eq!(checked.type_of("a"), "Float");
+    assert_eq!(checked.type_of("b"), "Color");
+    assert_eq!(checked.type_of("c"), "Float");
+    assert_eq!(checked.type_of("d"), "[Int]");
+}
+
+#[test]
+fn mismatches_with_annotations_point_at_them() {
+    let found = mismatches("x = (\"a\" :: Int)\ny = negate (True :: Bool)\n");
+    assert_eq!(
+        found,
+        [
+            ("Int".to_string(), Some(":: Int"), "String".to_string(), "\"a\""),
+            ("a number".to_string(), Some("negate"), "Bool".to_string(), "(True :: Bool)"),
+        ]
+    );
+}